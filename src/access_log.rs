@@ -0,0 +1,50 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    // time spent in restic processes while handling the current request
+    static RESTIC_TIME: Cell<Duration>;
+}
+
+// adds the runtime of a restic process to the current request's restic time, commands
+// run outside of a request, like background jobs, are not attributed to any request
+pub fn record_restic_time(elapsed: Duration) {
+    let _ = RESTIC_TIME.try_with(|total| total.set(total.get() + elapsed));
+}
+
+// middleware logging one line per request with its status, total duration and the time
+// spent in restic, no headers are logged so the api key can't end up in the log
+pub async fn access_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let started = Instant::now();
+
+    RESTIC_TIME
+        .scope(Cell::new(Duration::ZERO), async move {
+            let result = next.call(req).await;
+
+            let status = match &result {
+                Ok(res) => res.status().as_u16(),
+                Err(e) => e.as_response_error().status_code().as_u16(),
+            };
+            let restic_ms = RESTIC_TIME.with(|total| total.get().as_millis() as u64);
+            tracing::info!(
+                target: "access",
+                method,
+                path,
+                status,
+                duration_ms = started.elapsed().as_millis() as u64,
+                restic_ms,
+                "request finished"
+            );
+            result
+        })
+        .await
+}
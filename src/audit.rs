@@ -0,0 +1,143 @@
+use crate::auth::basic_credentials;
+use crate::etag::fnv1a;
+use crate::AppState;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use futures_util::stream;
+use serde_json::{json, Map, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+
+// GET endpoints that modify the repository and are audited like the other methods
+const MUTATING_GET_ROUTES: [&str; 1] = ["/backup/stream"];
+
+// request body fields copied into the audit entry, anything else, like the password of a
+// new key, is never written
+const AUDITED_FIELDS: [&str; 7] = [
+    "snapshot_id",
+    "snapshot_ids",
+    "target_dir",
+    "destination",
+    "paths",
+    "tags",
+    "dry_run",
+];
+
+// json bodies up to this size are inspected for AUDITED_FIELDS, larger ones and uploads
+// like /backup/stdin are passed through unread
+const MAX_INSPECTED_BODY: usize = 64 * 1024;
+
+// whether the request can modify the repository
+fn is_mutating(req: &ServiceRequest) -> bool {
+    match *req.method() {
+        Method::GET => req
+            .match_pattern()
+            .is_some_and(|pattern| MUTATING_GET_ROUTES.contains(&pattern.as_str())),
+        Method::HEAD | Method::OPTIONS => false,
+        _ => true,
+    }
+}
+
+// a short fingerprint of the api key the client sent, so entries can be told apart without
+// the key itself ending up in the log
+fn key_fingerprint(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get("X-API-Key")
+        .map(|key| format!("{:08x}", fnv1a(key.as_bytes()) >> 32))
+}
+
+// reads a small json body and puts it back for the handler, returns the audited fields
+async fn body_details(req: &mut ServiceRequest) -> Result<Map<String, Value>, Error> {
+    let is_json = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if !is_json || length.is_none_or(|length| length > MAX_INSPECTED_BODY) {
+        return Ok(Map::new());
+    }
+
+    let bytes = req.extract::<web::Bytes>().await?;
+    let body: Value = serde_json::from_slice(&bytes).unwrap_or_default();
+    req.set_payload(Payload::Stream {
+        payload: Box::pin(stream::once(async move { Ok(bytes) })),
+    });
+
+    Ok(AUDITED_FIELDS
+        .iter()
+        .filter_map(|field| Some((field.to_string(), body.get(field)?.clone())))
+        .collect())
+}
+
+// appends one json line to the audit log, the file is opened for every entry so it can be
+// rotated, and failures are logged since the request has already been handled
+async fn append(path: &str, entry: &Value) {
+    let line = format!("{}\n", entry);
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await
+    };
+    if let Err(e) = written.await {
+        tracing::error!(path, error = %e, "failed to write audit log entry");
+    }
+}
+
+// middleware recording every request that can modify the repository to
+// server.audit_log_path, with the client, the operation, its snapshot or target and the
+// outcome. it runs in front of the api key check so rejected attempts are recorded too
+pub async fn audit_log(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let path = match req.app_data::<web::Data<AppState>>() {
+        Some(data) if is_mutating(&req) => data.config.lock().await.server.audit_log_path.clone(),
+        _ => None,
+    };
+    let Some(path) = path.filter(|path| !path.is_empty()) else {
+        return next.call(req).await;
+    };
+
+    let mut entry = json!({
+        "timestamp": OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+        "client_ip": req.peer_addr().map(|addr| addr.ip().to_string()),
+        "api_key": key_fingerprint(&req),
+        "user": basic_credentials(&req).map(|(username, _)| username),
+        "method": req.method().as_str(),
+        "path": req.path(),
+        "query": Some(req.query_string()).filter(|query| !query.is_empty()),
+        "operation": req.match_pattern(),
+    });
+    entry["details"] = Value::Object(body_details(&mut req).await?);
+
+    let result = next.call(req).await;
+    let status = match &result {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    entry["status"] = json!(status.as_u16());
+    entry["result"] = json!(if status == StatusCode::ACCEPTED {
+        "started"
+    } else if status.is_success() {
+        "succeeded"
+    } else if status.is_client_error() {
+        "rejected"
+    } else {
+        "failed"
+    });
+
+    append(&path, &entry).await;
+    result
+}
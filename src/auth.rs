@@ -0,0 +1,110 @@
+use crate::{AppState, BasicAuthConfig};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::json;
+
+// header clients use to send the api key
+const API_KEY_HEADER: &str = "X-API-Key";
+
+// challenge sent with a 401 when basic auth is configured, so browsers ask for credentials
+const BASIC_CHALLENGE: &str = "Basic realm=\"restic-web-api\", charset=\"UTF-8\"";
+
+// the client a request was authenticated as, added to the request extensions once its
+// credentials were verified so the rate limit can tell clients apart by more than their ip
+#[derive(Clone)]
+pub struct ClientIdentity(pub String);
+
+// the username and password of an Authorization: Basic header
+pub fn basic_credentials(req: &ServiceRequest) -> Option<(String, String)> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+// checks basic credentials against the configured username and bcrypt hash and returns the
+// verified username, hashing is deliberately slow so it runs on the blocking thread pool
+async fn basic_auth_user(req: &ServiceRequest, expected: BasicAuthConfig) -> Option<String> {
+    let (username, password) = basic_credentials(req)?;
+    if !keys_match(&username, &expected.username) {
+        return None;
+    }
+    let verified =
+        web::block(move || bcrypt::verify(password, &expected.password_hash).unwrap_or(false))
+            .await
+            .unwrap_or(false);
+    verified.then_some(username)
+}
+
+// middleware that rejects requests without a matching X-API-Key header or basic auth
+// credentials, either one is enough when both are configured. requests pass through
+// untouched when neither is configured
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let (api_key, basic_auth) = match req.app_data::<web::Data<AppState>>() {
+        Some(data) => {
+            let config = data.current_config().await;
+            (
+                config.server.api_key.clone().filter(|key| !key.is_empty()),
+                config.server.basic_auth.clone(),
+            )
+        }
+        None => (None, None),
+    };
+
+    if api_key.is_some() || basic_auth.is_some() {
+        let provided = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let key_matches = api_key.is_some_and(|expected| keys_match(provided, &expected));
+
+        let challenge = basic_auth.is_some();
+        let identity = match basic_auth {
+            _ if key_matches => Some("key".to_string()),
+            Some(expected) => basic_auth_user(&req, expected)
+                .await
+                .map(|username| format!("user:{}", username)),
+            None => None,
+        };
+
+        let Some(identity) = identity else {
+            let mut response = HttpResponse::Unauthorized();
+            let error = if challenge {
+                response.insert_header((header::WWW_AUTHENTICATE, BASIC_CHALLENGE));
+                "Invalid or missing credentials"
+            } else {
+                "Invalid or missing API key"
+            };
+            let response = response.json(json!({ "error": error }));
+            return Ok(req.into_response(response).map_into_right_body());
+        };
+        req.extensions_mut().insert(ClientIdentity(identity));
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+// compares the keys without returning early on the first mismatching byte
+fn keys_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,410 @@
+use crate::jobs::{accepted, spawn_job, JobQuery};
+use crate::lock::lock_writes;
+use crate::restic::{
+    run_restic, run_restic_with_stream, spawn_restic, BandwidthLimits, ResticError,
+};
+use crate::sse::stream_restic_events;
+use crate::{AppState, Config};
+use actix_web::{get, post, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::process::Output;
+use utoipa::ToSchema;
+
+// request structure for the backup endpoint
+#[derive(Deserialize, ToSchema)]
+struct BackupRequest {
+    paths: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    // files with one exclude pattern per line, passed as --exclude-file
+    #[serde(default)]
+    exclude_files: Vec<String>,
+    // target size of the pack files in MiB, restic.pack_size_mb or restic's default when unset
+    pack_size_mb: Option<u32>,
+    #[serde(flatten)]
+    limits: BandwidthLimits,
+    // name of the file in the snapshot when the data is read from the request body
+    #[serde(skip)]
+    stdin_filename: Option<String>,
+}
+
+// a named backup from the [backups.<name>] tables of the config, run with
+// POST /backup/{profile} so clients don't have to send the paths every time
+#[derive(Deserialize, Serialize, Clone)]
+pub struct BackupProfile {
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub exclude_files: Vec<String>,
+    pub pack_size_mb: Option<u32>,
+}
+
+// pack sizes in MiB restic accepts for --pack-size
+pub const PACK_SIZE_RANGE_MB: RangeInclusive<u32> = 4..=128;
+
+// checks a pack size against the range restic accepts, field names the setting
+pub fn validate_pack_size(field: &str, pack_size_mb: Option<u32>) -> Result<(), String> {
+    match pack_size_mb {
+        Some(size) if !PACK_SIZE_RANGE_MB.contains(&size) => Err(format!(
+            "{} must be between {} and {}, got {}",
+            field,
+            PACK_SIZE_RANGE_MB.start(),
+            PACK_SIZE_RANGE_MB.end(),
+            size
+        )),
+        _ => Ok(()),
+    }
+}
+
+// profile names that would be shadowed by the other backup endpoints
+pub const RESERVED_PROFILE_NAMES: [&str; 1] = ["stdin"];
+
+impl From<&BackupProfile> for BackupRequest {
+    fn from(profile: &BackupProfile) -> Self {
+        BackupRequest {
+            paths: profile.paths.clone(),
+            tags: profile.tags.clone(),
+            exclude: profile.exclude.clone(),
+            exclude_files: profile.exclude_files.clone(),
+            pack_size_mb: profile.pack_size_mb,
+            limits: BandwidthLimits::default(),
+            stdin_filename: None,
+        }
+    }
+}
+
+impl BackupRequest {
+    // builds a request from repeated path, tag, exclude and exclude_file query parameters
+    // and the pack_size_mb, limit_upload, limit_download and stdin_filename parameters
+    fn from_query(params: &[(String, String)]) -> Result<Self, String> {
+        let values = |key: &str| {
+            params
+                .iter()
+                .filter(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+                .collect()
+        };
+
+        let limit = |key: &str| {
+            params
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| {
+                    value
+                        .parse()
+                        .map_err(|_| format!("{} must be a positive integer", key))
+                })
+                .transpose()
+        };
+
+        Ok(BackupRequest {
+            paths: values("path"),
+            tags: values("tag"),
+            exclude: values("exclude"),
+            exclude_files: values("exclude_file"),
+            pack_size_mb: limit("pack_size_mb")?,
+            limits: BandwidthLimits {
+                limit_upload: limit("limit_upload")?,
+                limit_download: limit("limit_download")?,
+            },
+            stdin_filename: params
+                .iter()
+                .find(|(name, _)| name == "stdin_filename")
+                .map(|(_, value)| value.clone()),
+        })
+    }
+
+    // checks that at least one non-empty path was given
+    fn has_paths(&self) -> bool {
+        self.paths.iter().any(|path| !path.trim().is_empty())
+    }
+
+    // checks that paths were given and that none of them looks like a flag
+    fn validate_paths(&self) -> Result<(), String> {
+        if !self.has_paths() {
+            return Err("At least one backup path is required".to_string());
+        }
+        match self.paths.iter().find(|path| path.starts_with('-')) {
+            Some(path) => Err(format!("Backup path \"{}\" must not start with -", path)),
+            None => Ok(()),
+        }
+    }
+
+    // checks that every exclude file can be read, restic would otherwise only fail once
+    // the backup has started
+    fn validate_exclude_files(&self) -> Result<(), String> {
+        for path in &self.exclude_files {
+            match fs::File::open(path).and_then(|file| file.metadata()) {
+                Ok(metadata) if metadata.is_file() => {}
+                Ok(_) => return Err(format!("Exclude file \"{}\" is not a file", path)),
+                Err(e) => return Err(format!("Exclude file \"{}\" can't be read: {}", path, e)),
+            }
+        }
+        Ok(())
+    }
+
+    // checks pack_size_mb against the range restic accepts
+    fn validate_pack_size(&self) -> Result<(), String> {
+        validate_pack_size("pack_size_mb", self.pack_size_mb)
+    }
+
+    // checks the whole request before restic runs, a backup from the request body takes a
+    // file name instead of paths and excludes
+    fn validate(&self) -> Result<(), String> {
+        match &self.stdin_filename {
+            Some(filename) => {
+                if filename.trim().is_empty() || filename.contains(['\n', '\r', '\0']) {
+                    return Err(
+                        "stdin_filename is required and must be a plain file name".to_string()
+                    );
+                }
+                if self.has_paths() || !self.exclude.is_empty() || !self.exclude_files.is_empty() {
+                    return Err("path, exclude and exclude_file can't be combined with a backup from the request body".to_string());
+                }
+            }
+            None => {
+                self.validate_paths()?;
+                self.validate_exclude_files()?;
+            }
+        }
+        self.limits.validate()?;
+        self.validate_pack_size()
+    }
+
+    // builds the restic backup arguments from the request, the pack size falls back to
+    // restic.pack_size_mb
+    fn args(&self, config: &Config) -> Vec<String> {
+        let mut args = vec!["backup".to_string(), "--json".to_string()];
+
+        // each tag and exclude pattern is passed as its own flag
+        for tag in &self.tags {
+            args.extend(["--tag".to_string(), tag.clone()]);
+        }
+        for pattern in &self.exclude {
+            args.extend(["--exclude".to_string(), pattern.clone()]);
+        }
+        for path in &self.exclude_files {
+            args.extend(["--exclude-file".to_string(), path.clone()]);
+        }
+        if let Some(size) = self.pack_size_mb.or(config.restic.pack_size_mb) {
+            args.extend(["--pack-size".to_string(), size.to_string()]);
+        }
+        args.extend(self.limits.args());
+        match &self.stdin_filename {
+            Some(filename) => args.extend([
+                "--stdin".to_string(),
+                "--stdin-filename".to_string(),
+                filename.clone(),
+            ]),
+            // the paths go after "--" so a leading dash isn't read as a flag
+            None => {
+                args.push("--".to_string());
+                args.extend(self.paths.iter().cloned());
+            }
+        }
+        args
+    }
+}
+
+// picks the summary from the json messages restic backup prints
+fn backup_summary(output: Output) -> Result<Value, ResticError> {
+    // restic prints one json object per line, the summary comes last
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .find(|message| message["message_type"] == "summary")
+        .ok_or_else(|| "Restic did not report a backup summary".into())
+}
+
+// function to create a new snapshot using restic backup
+async fn run_restic_backup(config: &Config, req: &BackupRequest) -> Result<Value, ResticError> {
+    let args = req.args(config);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    backup_summary(run_restic(config, &args).await?)
+}
+
+// endpoint to create a new snapshot (/backup)
+#[utoipa::path(
+    tag = "backup",
+    request_body = BackupRequest,
+    params(JobQuery),
+    responses(
+        (status = 200, description = "Backup summary", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/backup")]
+async fn backup(
+    data: web::Data<AppState>,
+    req: web::Json<BackupRequest>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+    start_backup(&data, &config, req.into_inner(), &job).await
+}
+
+// runs a backup request, or starts it as a background job with ?async=true
+async fn start_backup(
+    data: &web::Data<AppState>,
+    config: &Config,
+    req: BackupRequest,
+    job: &JobQuery,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(err) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": err })));
+    }
+
+    let guard = match lock_writes(data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+
+    if job.background {
+        let config = config.clone();
+        let job_id = spawn_job(data, "backup", async move {
+            let _guard = guard;
+            run_restic_backup(&config, &req).await
+        })
+        .await;
+        return Ok(accepted(job_id));
+    }
+
+    let summary = run_restic_backup(config, &req).await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// endpoint running a backup profile from the config (/backup/{profile})
+#[utoipa::path(
+    tag = "backup",
+    params(("profile" = String, Path, description = "Name of a [backups.<name>] table in the config"), JobQuery),
+    responses(
+        (status = 200, description = "Backup summary", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 400, description = "An exclude file of the profile can't be read", body = crate::ErrorResponse),
+        (status = 404, description = "Unknown profile", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/backup/{profile}")]
+async fn backup_profile(
+    data: web::Data<AppState>,
+    profile: web::Path<String>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let Some(req) = config
+        .backups
+        .get(profile.as_str())
+        .map(BackupRequest::from)
+    else {
+        return Ok(HttpResponse::NotFound()
+            .json(json!({ "error": format!("Unknown backup profile \"{}\"", profile) })));
+    };
+    start_backup(&data, &config, req, &job).await
+}
+
+// endpoint streaming backup progress as server-sent events
+// (/backup/stream?path=/home&tag=nightly&exclude=*.tmp)
+#[utoipa::path(
+    tag = "backup",
+    params(("path" = Vec<String>, Query, description = "Path to back up, may be repeated"), ("tag" = Option<Vec<String>>, Query, description = "Tag, may be repeated"), ("exclude" = Option<Vec<String>>, Query, description = "Exclude pattern, may be repeated"), ("exclude_file" = Option<Vec<String>>, Query, description = "File with exclude patterns, may be repeated"), ("limit_upload" = Option<u32>, Query, description = "Upload limit in KiB/s"), ("limit_download" = Option<u32>, Query, description = "Download limit in KiB/s")),
+    responses(
+        (status = 200, description = "Progress messages as server-sent events", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/backup/stream")]
+async fn backup_stream(
+    data: web::Data<AppState>,
+    query: web::Query<Vec<(String, String)>>,
+) -> Result<HttpResponse, ResticError> {
+    let req = match BackupRequest::from_query(&query) {
+        Ok(req) => req,
+        Err(err) => return Ok(HttpResponse::BadRequest().json(json!({ "error": err }))),
+    };
+    if req.stdin_filename.is_some() {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "stdin_filename is only accepted by /backup/stdin" })));
+    }
+    if let Err(err) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": err })));
+    }
+
+    let guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+    let config = data.current_config().await;
+
+    let args = req.args(&config);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let child = spawn_restic(&config, &args).await?;
+    Ok(stream_restic_events(child, guard))
+}
+
+// endpoint to back up the request body as a single file
+// (/backup/stdin?stdin_filename=db.sql&tag=nightly), e.g. a database dump piped with curl
+#[utoipa::path(
+    tag = "backup",
+    params(("stdin_filename" = String, Query, description = "Name of the file in the snapshot"), ("tag" = Option<Vec<String>>, Query, description = "Tag, may be repeated"), ("limit_upload" = Option<u32>, Query, description = "Upload limit in KiB/s"), ("limit_download" = Option<u32>, Query, description = "Download limit in KiB/s")),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream", description = "Data to back up"),
+    responses(
+        (status = 200, description = "Backup summary", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed or the body couldn't be read", body = crate::ErrorResponse),
+    )
+)]
+#[post("/backup/stdin")]
+async fn backup_stdin(
+    data: web::Data<AppState>,
+    query: web::Query<Vec<(String, String)>>,
+    body: web::Payload,
+) -> Result<HttpResponse, ResticError> {
+    let mut req = match BackupRequest::from_query(&query) {
+        Ok(req) => req,
+        Err(err) => return Ok(HttpResponse::BadRequest().json(json!({ "error": err }))),
+    };
+    // a missing stdin_filename is rejected like an empty one
+    req.stdin_filename.get_or_insert_default();
+    if let Err(err) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": err })));
+    }
+
+    let _guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+    let config = data.current_config().await;
+
+    let args = req.args(&config);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let output = run_restic_with_stream(&config, &args, body).await?;
+    Ok(HttpResponse::Ok().json(backup_summary(output)?))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    // registered last so /backup/stdin isn't taken for a profile name
+    cfg.service(backup)
+        .service(backup_stream)
+        .service(backup_stdin)
+        .service(backup_profile);
+}
@@ -0,0 +1,215 @@
+use crate::optional_json::OptionalJson;
+use crate::restic::{run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{get, post, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+
+// restic's default for how many days an unused cache directory is kept
+const DEFAULT_MAX_AGE_DAYS: u32 = 30;
+
+// units restic formats sizes with, and their size in bytes
+const SIZE_UNITS: [(&str, u64); 5] = [
+    ("B", 1),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+];
+
+// request structure for the cache clear endpoint, the body is optional
+#[derive(Deserialize, Default, ToSchema)]
+struct CacheClearRequest {
+    // cache directories unused for longer than this are removed, 30 when not set
+    max_age_days: Option<u32>,
+}
+
+// one repository cache directory as listed by restic cache
+#[derive(Serialize, ToSchema, Debug, PartialEq)]
+struct CacheEntry {
+    // id of the repository the cache belongs to
+    id: String,
+    last_used_days: Option<u64>,
+    // unused for longer than the max age, removed by a cleanup
+    old: bool,
+    size_bytes: Option<u64>,
+}
+
+// the cache directory and its repository caches
+#[derive(Serialize, ToSchema, Debug, PartialEq)]
+struct CacheListing {
+    directory: Option<String>,
+    caches: Vec<CacheEntry>,
+    total_size_bytes: u64,
+}
+
+// reads a size like "1.500 MiB" from restic's cache table
+fn parse_size(value: &str, unit: &str) -> Option<u64> {
+    let (_, factor) = SIZE_UNITS.iter().find(|(name, _)| *name == unit)?;
+    let value: f64 = value.parse().ok()?;
+    Some((value * *factor as f64).round() as u64)
+}
+
+// reads one row of restic's cache table, like "a1b2c3d4e5  2 days ago  yes  1.500 MiB"
+fn parse_entry(line: &str) -> Option<CacheEntry> {
+    let mut tokens: Vec<&str> = line.split_whitespace().collect();
+    let id = tokens.first()?.to_string();
+    if !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    tokens.remove(0);
+
+    let size_bytes = match tokens.as_slice() {
+        [.., value, unit] => parse_size(value, unit),
+        _ => None,
+    };
+    if size_bytes.is_some() {
+        tokens.truncate(tokens.len() - 2);
+    }
+    let old = tokens.last() == Some(&"yes");
+    if old {
+        tokens.pop();
+    }
+
+    Some(CacheEntry {
+        id,
+        last_used_days: tokens.first().and_then(|days| days.parse().ok()),
+        old,
+        size_bytes,
+    })
+}
+
+// parses the table restic cache prints, followed by "2 cache dirs in /root/.cache/restic",
+// or "no cache dirs found, basedir is ..." when there are none
+fn parse_cache_listing(stdout: &str) -> CacheListing {
+    // the rows are framed by lines of dashes
+    let caches: Vec<CacheEntry> = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with('-'))
+        .skip(1)
+        .take_while(|line| !line.starts_with('-'))
+        .filter_map(parse_entry)
+        .collect();
+    let directory = stdout.lines().find_map(|line| {
+        line.split_once(" cache dirs in ")
+            .or_else(|| line.split_once("basedir is "))
+            .map(|(_, directory)| directory.trim().to_string())
+    });
+
+    CacheListing {
+        directory,
+        total_size_bytes: caches.iter().filter_map(|cache| cache.size_bytes).sum(),
+        caches,
+    }
+}
+
+// function to list the local cache directories using restic cache
+async fn list_restic_cache(
+    config: &Config,
+    max_age_days: u32,
+) -> Result<CacheListing, ResticError> {
+    let max_age = max_age_days.to_string();
+    let output = run_restic(config, &["cache", "--max-age", &max_age]).await?;
+    Ok(parse_cache_listing(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+// endpoint reporting the local cache directories and their sizes (/cache)
+#[utoipa::path(
+    tag = "repository",
+    responses(
+        (status = 200, description = "Cache directory and the caches in it", body = CacheListing),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/cache")]
+async fn list_cache(data: web::Data<AppState>) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let listing = list_restic_cache(&config, DEFAULT_MAX_AGE_DAYS).await?;
+    Ok(HttpResponse::Ok().json(listing))
+}
+
+// endpoint removing old cache directories with restic cache --cleanup (/cache/clear),
+// restic doesn't report what it removed, so the old caches are listed beforehand
+#[utoipa::path(
+    tag = "repository",
+    request_body(content = Option<CacheClearRequest>),
+    responses(
+        (status = 200, description = "Removed caches and the space freed", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/cache/clear")]
+async fn clear_cache(
+    data: web::Data<AppState>,
+    OptionalJson(req): OptionalJson<CacheClearRequest>,
+) -> Result<HttpResponse, ResticError> {
+    let max_age_days = req.max_age_days.unwrap_or(DEFAULT_MAX_AGE_DAYS);
+    if max_age_days == 0 {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "max_age_days must be a positive integer" })));
+    }
+
+    let config = data.current_config().await;
+
+    let listing = list_restic_cache(&config, max_age_days).await?;
+    let max_age = max_age_days.to_string();
+    let output = run_restic(&config, &["cache", "--cleanup", "--max-age", &max_age]).await?;
+
+    let removed: Vec<&CacheEntry> = listing.caches.iter().filter(|cache| cache.old).collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "removed": removed.iter().map(|cache| &cache.id).collect::<Vec<_>>(),
+        "freed_bytes": removed.iter().filter_map(|cache| cache.size_bytes).sum::<u64>(),
+        "output": String::from_utf8_lossy(&output.stdout).trim(),
+    })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_cache).service(clear_cache);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_cache_table() {
+        let stdout = "Repo ID     Last Used   Old  Size\n\
+                      ----------------------------------------\n\
+                      a1b2c3d4e5  2 days ago        1.500 MiB\n\
+                      f6e7d8c9b0  45 days ago  yes  512 B\n\
+                      ----------------------------------------\n\
+                      2 cache dirs in /root/.cache/restic\n";
+        let listing = parse_cache_listing(stdout);
+        assert_eq!(listing.directory.as_deref(), Some("/root/.cache/restic"));
+        assert_eq!(
+            listing.caches,
+            vec![
+                CacheEntry {
+                    id: "a1b2c3d4e5".to_string(),
+                    last_used_days: Some(2),
+                    old: false,
+                    size_bytes: Some(1_572_864),
+                },
+                CacheEntry {
+                    id: "f6e7d8c9b0".to_string(),
+                    last_used_days: Some(45),
+                    old: true,
+                    size_bytes: Some(512),
+                },
+            ]
+        );
+        assert_eq!(listing.total_size_bytes, 1_573_376);
+    }
+
+    #[test]
+    fn parses_an_empty_cache() {
+        let listing = parse_cache_listing("no cache dirs found, basedir is /root/.cache/restic\n");
+        assert!(listing.caches.is_empty());
+        assert_eq!(listing.directory.as_deref(), Some("/root/.cache/restic"));
+    }
+}
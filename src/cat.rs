@@ -0,0 +1,45 @@
+use crate::restic::{parse_json, run_restic, validate_snapshot_id, ResticError};
+use crate::{AppState, Config};
+use actix_web::{get, web, HttpResponse};
+use serde_json::{json, Value};
+
+// function to read the metadata of a single snapshot using restic cat
+async fn cat_restic_snapshot(config: &Config, snapshot_id: &str) -> Result<Value, ResticError> {
+    let output = run_restic(config, &["cat", "snapshot", snapshot_id]).await?;
+    parse_json(output)
+}
+
+// endpoint returning the raw metadata of a snapshot (/snapshots/{id}/cat)
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID")),
+    responses(
+        (status = 200, description = "Raw snapshot metadata", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/snapshots/{id}/cat")]
+async fn cat(
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(&id) {
+        return Ok(response);
+    }
+
+    let config = data.current_config().await;
+
+    match cat_restic_snapshot(&config, &id).await {
+        Ok(snapshot) => Ok(HttpResponse::Ok().json(snapshot)),
+        Err(err) if err.is_snapshot_not_found() => {
+            Ok(HttpResponse::NotFound().json(json!({ "error": "snapshot not found" })))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(cat);
+}
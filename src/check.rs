@@ -0,0 +1,77 @@
+use crate::jobs::{accepted, spawn_job, JobQuery};
+use crate::optional_json::OptionalJson;
+use crate::restic::{run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+// request structure for the check endpoint, the body is optional
+#[derive(Deserialize, Default, ToSchema)]
+struct CheckRequest {
+    #[serde(default)]
+    read_data: bool,
+}
+
+// function to verify the repository integrity using restic check
+async fn run_restic_check(config: &Config, read_data: bool) -> Result<String, ResticError> {
+    let mut args = vec!["check"];
+    if read_data {
+        args.push("--read-data");
+    }
+
+    let output = run_restic(config, &args).await?;
+
+    // restic reports progress on stdout and warnings on stderr, keep both
+    let mut report = String::from_utf8_lossy(&output.stdout).into_owned();
+    report.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(report)
+}
+
+// endpoint to check the repository for errors (/check)
+#[utoipa::path(
+    tag = "repository",
+    request_body(content = Option<CheckRequest>),
+    params(JobQuery),
+    responses(
+        (status = 200, description = "Check report", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 403, description = "Wrong repository password", body = crate::ErrorResponse),
+        (status = 423, description = "Repository is locked", body = crate::ErrorResponse),
+        (status = 500, description = "The check found errors, or restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/check")]
+async fn check(
+    data: web::Data<AppState>,
+    OptionalJson(req): OptionalJson<CheckRequest>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    if job.background {
+        let config = config.clone();
+        let job_id = spawn_job(&data, "check", async move {
+            let output = run_restic_check(&config, req.read_data).await?;
+            Ok(json!({ "output": output }))
+        })
+        .await;
+        return Ok(accepted(job_id));
+    }
+
+    match run_restic_check(&config, req.read_data).await {
+        Ok(output) => Ok(HttpResponse::Ok().json(json!({ "success": true, "output": output }))),
+        // restic exits with 1 when the check itself found errors in the repository, every
+        // other failure is answered like on the other endpoints
+        Err(err @ ResticError::Failed(_)) if err.exit_code() == Some(1) => {
+            Ok(HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": err.to_string() })))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(check);
+}
@@ -0,0 +1,326 @@
+use crate::backup::{validate_pack_size, RESERVED_PROFILE_NAMES};
+use crate::restic::restic_version;
+use crate::{Config, RepositoryConfig, ResticConfig};
+use tokio::process::Command;
+
+// repository location prefixes restic understands, anything else with a colon is a typo
+const BACKENDS: [&str; 10] = [
+    "local", "sftp", "rest", "s3", "b2", "azure", "gs", "swift", "rclone", "smb",
+];
+
+// checks that a repository location is a local path or uses a backend restic knows
+fn check_repository_path(name: &str, path: &str) -> Result<(), String> {
+    if path.trim().is_empty() {
+        return Err(format!("{} is empty", name));
+    }
+    if path.contains(['\n', '\r', '\0']) {
+        return Err(format!("{} contains control characters", name));
+    }
+
+    // absolute and explicitly relative paths may contain colons
+    if path.starts_with(['/', '.', '~']) {
+        return Ok(());
+    }
+
+    match path.split_once(':') {
+        // a windows drive letter
+        Some((drive, _)) if drive.len() == 1 => Ok(()),
+        Some((backend, location)) if BACKENDS.contains(&backend) => {
+            if location.is_empty() {
+                Err(format!("{} has no location after \"{}:\"", name, backend))
+            } else {
+                Ok(())
+            }
+        }
+        Some((backend, _)) => Err(format!(
+            "{} uses the unknown backend \"{}\", expected one of {} or a local path",
+            name,
+            backend,
+            BACKENDS.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+// checks that a restic binary given as a path exists and is executable, bare names are
+// looked up on PATH when restic runs
+fn check_binary_path(path: &str) -> Result<(), String> {
+    if !path.contains(std::path::MAIN_SEPARATOR) && !path.contains('/') {
+        return Ok(());
+    }
+
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| format!("restic.binary_path \"{}\" can't be read: {}", path, e))?;
+    if !metadata.is_file() {
+        return Err(format!("restic.binary_path \"{}\" is not a file", path));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(format!("restic.binary_path \"{}\" is not executable", path));
+        }
+    }
+    Ok(())
+}
+
+// io scheduling classes accepted for restic.ionice, realtime would need root and could
+// starve the host instead
+const IONICE_CLASSES: [&str; 2] = ["idle", "best-effort"];
+
+// whether a program can be found in one of the PATH directories
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+// checks the nice and ionice settings and that the programs they need are installed,
+// settings the platform doesn't support are skipped since they are ignored anyway
+fn check_priority(restic: &ResticConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(nice) = restic.nice {
+        if !(-20..=19).contains(&nice) {
+            problems.push(format!(
+                "restic.nice must be between -20 and 19, got {}",
+                nice
+            ));
+        } else if cfg!(unix) && !on_path("nice") {
+            problems.push("restic.nice is set but nice is not installed".to_string());
+        }
+    }
+    match restic.ionice.as_deref() {
+        Some(class) if !IONICE_CLASSES.contains(&class) => problems.push(format!(
+            "restic.ionice must be one of {}, got \"{}\"",
+            IONICE_CLASSES.join(", "),
+            class
+        )),
+        Some(_) if cfg!(target_os = "linux") && !on_path("ionice") => {
+            problems.push("restic.ionice is set but ionice is not installed".to_string())
+        }
+        _ => {}
+    }
+    match (restic.ionice.as_deref(), restic.ionice_level) {
+        (_, Some(level)) if level > 7 => problems.push(format!(
+            "restic.ionice_level must be between 0 and 7, got {}",
+            level
+        )),
+        (Some("best-effort"), _) | (_, None) => {}
+        (_, Some(_)) => problems
+            .push("restic.ionice_level only applies to the best-effort ionice class".to_string()),
+    }
+    problems
+}
+
+// checks that the audit log can be appended to, creating it if it doesn't exist yet, so
+// mutating requests aren't served without an audit trail
+fn check_audit_log_path(path: &str) -> Result<(), String> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(drop)
+        .map_err(|e| format!("server.audit_log_path \"{}\" can't be written: {}", path, e))
+}
+
+// checks the repository locations, the restic binary and its priority, the audit log and
+// the backup profiles of a loaded config, run before the server starts
+pub fn check_repositories(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = check_binary_path(config.restic.binary_path()) {
+        problems.push(e);
+    }
+    problems.extend(check_priority(&config.restic));
+    if let Err(e) = validate_pack_size("restic.pack_size_mb", config.restic.pack_size_mb) {
+        problems.push(e);
+    }
+    if let Some(dir) = config.server.default_restore_dir.as_deref() {
+        if !std::path::Path::new(dir).is_absolute() {
+            problems.push(format!(
+                "server.default_restore_dir must be an absolute path, got \"{}\"",
+                dir
+            ));
+        }
+    }
+    if let Some(path) = config.server.audit_log_path.as_deref() {
+        if let Err(e) = check_audit_log_path(path) {
+            problems.push(e);
+        }
+    }
+    if let Err(e) = check_repository_path("repository.path", config.repository.path()) {
+        problems.push(e);
+    }
+    for (name, destination) in &config.destinations {
+        let field = format!("destinations.{}.path", name);
+        if let Err(e) = check_repository_path(&field, destination.path()) {
+            problems.push(e);
+        }
+    }
+    problems.extend(check_backup_profiles(config));
+    problems
+}
+
+// checks that every backup profile backs up something and can be reached, the exclude
+// files are only checked when the profile runs since they may be created later
+fn check_backup_profiles(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (name, profile) in &config.backups {
+        if RESERVED_PROFILE_NAMES.contains(&name.as_str()) {
+            problems.push(format!(
+                "backups.{} can't be used as a profile name, POST /backup/{} is a different endpoint",
+                name, name
+            ));
+        }
+        if !profile.paths.iter().any(|path| !path.trim().is_empty()) {
+            problems.push(format!(
+                "backups.{}.paths must list at least one path",
+                name
+            ));
+        }
+        let field = format!("backups.{}.pack_size_mb", name);
+        if let Err(e) = validate_pack_size(&field, profile.pack_size_mb) {
+            problems.push(e);
+        }
+    }
+    problems
+}
+
+// splits a command into its words like restic splits --password-command: at whitespace,
+// with single and double quotes keeping words together and a backslash escaping the next
+// character outside of single quotes. there is no shell, so no variables or pipes
+fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // a quoted empty string is still a word
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some('"') | None, '\\') => match chars.next() {
+                Some(escaped) => {
+                    word.push(escaped);
+                    in_word = true;
+                }
+                None => return Err("ends with a backslash".to_string()),
+            },
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err("has an unterminated quote".to_string());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// runs the password command the way restic would and checks that it prints a password
+pub async fn check_password_command(
+    name: &str,
+    repository: &RepositoryConfig,
+) -> Result<(), String> {
+    let Some(password_command) = repository.password_command() else {
+        return Ok(());
+    };
+    let words =
+        split_command(password_command).map_err(|e| format!("{}.password_command {}", name, e))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(format!("{}.password_command is empty", name));
+    };
+
+    let output = Command::new(program)
+        .args(args)
+        .envs(&repository.env)
+        .output()
+        .await
+        .map_err(|e| format!("{}.password_command could not be run: {}", name, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "{}.password_command failed with {}: {}",
+            name,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    if String::from_utf8_lossy(&output.stdout).trim().is_empty() {
+        return Err(format!("{}.password_command printed no password", name));
+    }
+    Ok(())
+}
+
+// runs every check for --check-config, including ones that execute commands,
+// and returns the detected restic version or the problems found
+pub async fn check_config(config: &Config) -> Result<String, Vec<String>> {
+    let mut problems = check_repositories(config);
+
+    if let Err(e) = check_password_command("repository", &config.repository).await {
+        problems.push(e);
+    }
+    for (name, destination) in &config.destinations {
+        let field = format!("destinations.{}", name);
+        if let Err(e) = check_password_command(&field, destination).await {
+            problems.push(e);
+        }
+    }
+
+    let version = match restic_version(config.restic.binary_path()).await {
+        Ok(version) => Some(version),
+        Err(e) => {
+            problems.push(format!(
+                "restic could not be run, is it installed and on PATH? {}",
+                e
+            ));
+            None
+        }
+    };
+
+    match version {
+        Some(version) if problems.is_empty() => Ok(version),
+        _ => Err(problems),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_split_into_words() {
+        assert_eq!(
+            split_command("pass show  restic/repository").unwrap(),
+            ["pass", "show", "restic/repository"]
+        );
+        assert_eq!(
+            split_command(r#"cat "/etc/restic/my password" 'a "b"' c\ d "" e"f"g"#).unwrap(),
+            [
+                "cat",
+                "/etc/restic/my password",
+                "a \"b\"",
+                "c d",
+                "",
+                "efg"
+            ]
+        );
+        assert!(split_command("cat 'unterminated").is_err());
+        assert!(split_command("cat trailing\\").is_err());
+    }
+}
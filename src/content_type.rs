@@ -0,0 +1,26 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, CONTENT_TYPE};
+use actix_web::middleware::Next;
+use actix_web::Error;
+
+// content type of every json response, strict clients reject json without a charset
+pub const JSON_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+
+// middleware adding the charset to json responses, actix's HttpResponse::json and the
+// error responses built with it only send application/json
+pub async fn json_charset(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut res = next.call(req).await?;
+
+    let headers = res.headers_mut();
+    if headers
+        .get(CONTENT_TYPE)
+        .is_some_and(|value| value == "application/json")
+    {
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE));
+    }
+    Ok(res)
+}
@@ -0,0 +1,128 @@
+use crate::jobs::{accepted, spawn_job, JobQuery};
+use crate::restic::{run_restic, validate_snapshot_id, ResticError};
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+// request structure for the copy endpoint, destination names a [destinations.<name>] table
+#[derive(Deserialize, ToSchema)]
+struct CopyRequest {
+    destination: String,
+}
+
+// picks the ids of the snapshots restic reports as saved in the destination,
+// "snapshot 1a2b3c4d saved" for new copies
+fn copied_snapshot_ids(report: &str) -> Vec<String> {
+    report
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("snapshot"), Some(id), Some("saved")) => Some(id.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// builds a config that runs restic against the destination and reads from the source
+// repository, restic expects the source password in the RESTIC_FROM_* variables
+fn destination_config(config: &Config, name: &str) -> Option<Config> {
+    let destination = config.destinations.get(name)?;
+    let source = &config.repository;
+
+    let mut target = config.clone();
+    target.repository = destination.clone();
+
+    // the source backend credentials are needed too, the destination's win on conflicts
+    for (key, value) in &source.env {
+        target
+            .repository
+            .env
+            .entry(key.clone())
+            .or_insert_with(|| value.clone());
+    }
+    match source.password_command() {
+        Some(password_command) => {
+            target.repository.env.insert(
+                "RESTIC_FROM_PASSWORD_COMMAND".to_string(),
+                password_command.to_string(),
+            );
+        }
+        None => {
+            target.repository.env.insert(
+                "RESTIC_FROM_PASSWORD".to_string(),
+                source.password().to_string(),
+            );
+        }
+    }
+    Some(target)
+}
+
+// function to copy a snapshot into another repository using restic copy
+async fn copy_restic_snapshot(
+    config: &Config,
+    source_path: &str,
+    snapshot_id: &str,
+) -> Result<Value, ResticError> {
+    let output = run_restic(config, &["copy", "--from-repo", source_path, snapshot_id]).await?;
+
+    // restic copy has no json output, a snapshot that was copied before is skipped
+    let report = String::from_utf8_lossy(&output.stdout).into_owned();
+    Ok(json!({
+        "snapshot_ids": copied_snapshot_ids(&report),
+        "output": report,
+    }))
+}
+
+// endpoint to copy a snapshot to a configured destination repository (/snapshots/{id}/copy)
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID"), JobQuery),
+    request_body = CopyRequest,
+    responses(
+        (status = 200, description = "IDs of the snapshots created in the destination", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 400, description = "Invalid snapshot ID", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/snapshots/{id}/copy")]
+async fn copy_snapshot(
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+    req: web::Json<CopyRequest>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(&id) {
+        return Ok(response);
+    }
+
+    let config = data.current_config().await;
+
+    let Some(copy_config) = destination_config(&config, &req.destination) else {
+        return Ok(HttpResponse::NotFound().json(json!({
+            "error": format!("Destination {} is not configured", req.destination)
+        })));
+    };
+    let source_path = config.repository.path().to_string();
+    let snapshot_id = id.into_inner();
+
+    if job.background {
+        let job_id = spawn_job(&data, "copy", async move {
+            copy_restic_snapshot(&copy_config, &source_path, &snapshot_id).await
+        })
+        .await;
+        return Ok(accepted(job_id));
+    }
+
+    let json = copy_restic_snapshot(&copy_config, &source_path, &snapshot_id).await?;
+    Ok(HttpResponse::Ok().json(json))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(copy_snapshot);
+}
@@ -0,0 +1,129 @@
+use crate::locks::list_lock_ids;
+use crate::restic::ResticError;
+use crate::snapshot::Snapshot;
+use crate::stats::get_restic_snapshot_stats;
+use crate::{
+    get_restic_snapshots, newest_snapshot, AppState, Config, ErrorResponse, SnapshotsQuery,
+};
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+// query parameters for the dashboard endpoint
+#[derive(Deserialize, IntoParams)]
+struct DashboardQuery {
+    // read the stats and snapshots without locking the repository
+    #[serde(default)]
+    no_lock: bool,
+}
+
+// a dashboard section that couldn't be loaded, with the status and body the section's
+// own endpoint would have answered with
+#[derive(Serialize)]
+struct SectionError {
+    status: u16,
+    #[serde(flatten)]
+    error: ErrorResponse,
+}
+
+// one section of the dashboard, either its data or why it failed
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Section<T> {
+    Loaded(T),
+    Failed(SectionError),
+}
+
+impl<T> From<Result<T, ResticError>> for Section<T> {
+    fn from(result: Result<T, ResticError>) -> Self {
+        match result {
+            Ok(value) => Section::Loaded(value),
+            Err(err) => Section::Failed(SectionError {
+                status: err.status().as_u16(),
+                error: err.body(),
+            }),
+        }
+    }
+}
+
+// the newest snapshot and how many there are, both from one snapshots listing
+#[derive(Serialize)]
+struct SnapshotsSummary {
+    count: usize,
+    // null for a repository without snapshots
+    latest: Option<Snapshot>,
+}
+
+// the locks currently held on the repository
+#[derive(Serialize)]
+struct LockStatus {
+    locked: bool,
+    lock_count: usize,
+}
+
+// everything a dashboard shows, every section is loaded on its own
+#[derive(Serialize, ToSchema)]
+struct Dashboard {
+    #[schema(value_type = Object)]
+    stats: Section<Value>,
+    #[schema(value_type = Object)]
+    snapshots: Section<SnapshotsSummary>,
+    #[schema(value_type = Object)]
+    locks: Section<LockStatus>,
+}
+
+// lists the snapshots and picks the newest one
+async fn snapshots_summary(
+    config: &Config,
+    no_lock: bool,
+) -> Result<SnapshotsSummary, ResticError> {
+    let filter = SnapshotsQuery {
+        no_lock,
+        ..Default::default()
+    };
+    let snapshots = get_restic_snapshots(config, &filter).await?;
+    Ok(SnapshotsSummary {
+        count: snapshots.len(),
+        latest: newest_snapshot(snapshots),
+    })
+}
+
+// counts the lock files in the repository
+async fn lock_status(config: &Config) -> Result<LockStatus, ResticError> {
+    let lock_count = list_lock_ids(config).await?.len();
+    Ok(LockStatus {
+        locked: lock_count > 0,
+        lock_count,
+    })
+}
+
+// endpoint combining the stats, the latest snapshot, the snapshot count and the lock
+// status (/dashboard), the sections are loaded in parallel and a failing one is reported
+// in its place instead of failing the whole response
+#[utoipa::path(
+    tag = "repository",
+    params(DashboardQuery),
+    responses(
+        (status = 200, description = "The dashboard sections, a failed section holds its status and error instead", body = Dashboard),
+    )
+)]
+#[get("/dashboard")]
+async fn dashboard(data: web::Data<AppState>, query: web::Query<DashboardQuery>) -> HttpResponse {
+    let config = data.current_config().await;
+
+    let (stats, snapshots, locks) = tokio::join!(
+        get_restic_snapshot_stats(&config, None, None, query.no_lock),
+        snapshots_summary(&config, query.no_lock),
+        lock_status(&config),
+    );
+    HttpResponse::Ok().json(Dashboard {
+        stats: stats.into(),
+        snapshots: snapshots.into(),
+        locks: locks.into(),
+    })
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(dashboard);
+}
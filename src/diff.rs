@@ -0,0 +1,90 @@
+use crate::restic::{parse_json_lines, run_restic, validate_snapshot_id, ResticError};
+use crate::{AppState, Config};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::IntoParams;
+
+// query parameters for the diff endpoint
+#[derive(Deserialize, IntoParams)]
+struct DiffQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+// groups the change messages restic prints by modifier and keeps the final statistics,
+// "+" marks added paths, "-" removed ones and anything else a modification
+fn summarize_diff(messages: Vec<Value>) -> Value {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut modified = Vec::new();
+    let mut statistics = Value::Null;
+
+    for message in messages {
+        match message["message_type"].as_str() {
+            Some("change") => {
+                let path = message["path"].clone();
+                match message["modifier"].as_str() {
+                    Some("+") => added.push(path),
+                    Some("-") => removed.push(path),
+                    _ => modified.push(json!({ "path": path, "modifier": message["modifier"] })),
+                }
+            }
+            Some("statistics") => statistics = message,
+            _ => {}
+        }
+    }
+
+    json!({
+        "added": added,
+        "removed": removed,
+        "modified": modified,
+        "statistics": statistics,
+    })
+}
+
+// function to compare two snapshots using restic diff
+async fn diff_restic_snapshots(
+    config: &Config,
+    from: &str,
+    to: &str,
+) -> Result<Value, ResticError> {
+    let output = run_restic(config, &["diff", from, to, "--json"]).await?;
+    parse_json_lines(output).map(summarize_diff)
+}
+
+// endpoint to compare two snapshots (/diff?from={id}&to={id})
+#[utoipa::path(
+    tag = "snapshots",
+    params(DiffQuery),
+    responses(
+        (status = 200, description = "Added, removed and modified paths with statistics", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/diff")]
+async fn diff(
+    data: web::Data<AppState>,
+    query: web::Query<DiffQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let (Some(from), Some(to)) = (
+        query.from.as_deref().filter(|id| !id.trim().is_empty()),
+        query.to.as_deref().filter(|id| !id.trim().is_empty()),
+    ) else {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "Both from and to snapshot IDs are required" })));
+    };
+    if let Err(response) = validate_snapshot_id(from).and(validate_snapshot_id(to)) {
+        return Ok(response);
+    }
+
+    let config = data.current_config().await;
+
+    let summary = diff_restic_snapshots(&config, from, to).await?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(diff);
+}
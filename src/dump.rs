@@ -0,0 +1,150 @@
+use crate::restic::{spawn_restic, validate_snapshot_id, ResticError, ResticProcess};
+use crate::AppState;
+use actix_web::http::header::ContentDisposition;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::io::AsyncReadExt;
+use tokio::process::ChildStdout;
+use tokio::task::JoinHandle;
+use tokio_util::io::ReaderStream;
+use utoipa::IntoParams;
+
+// query parameters for the dump endpoint
+#[derive(Deserialize, IntoParams)]
+struct DumpQuery {
+    path: String,
+}
+
+// state carried between the chunks of a dumped file
+struct DumpStream {
+    chunks: ReaderStream<ChildStdout>,
+    process: ResticProcess,
+    stderr: Option<JoinHandle<String>>,
+    finished: bool,
+}
+
+impl DumpStream {
+    // waits for restic to exit and returns its failure, if it failed
+    async fn exit_error(&mut self) -> Option<ResticError> {
+        let exit_code = match self.process.child.wait().await {
+            Ok(status) if status.success() => return None,
+            Ok(status) => status.code(),
+            Err(_) => None,
+        };
+        let stderr = match self.stderr.take() {
+            Some(handle) => handle.await.unwrap_or_default(),
+            None => String::new(),
+        };
+        Some(ResticError::failed(exit_code, &stderr))
+    }
+}
+
+// reads the next chunk of the file, a restic failing mid-file ends the stream with an
+// error so the client sees an aborted download instead of a truncated file
+async fn next_chunk(
+    mut state: DumpStream,
+) -> Option<(Result<Bytes, actix_web::Error>, DumpStream)> {
+    if state.finished {
+        return None;
+    }
+
+    let err = match state.chunks.next().await {
+        Some(Ok(chunk)) => return Some((Ok(chunk), state)),
+        Some(Err(e)) => ResticError::from(format!("Failed to read restic output: {}", e)),
+        None => state.exit_error().await?,
+    };
+
+    state.finished = true;
+    tracing::warn!(error = %err, "aborting streamed response");
+    Some((Err(err.into()), state))
+}
+
+// endpoint to download a single file from a snapshot (/snapshots/{id}/dump)
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID"), DumpQuery),
+    responses(
+        (status = 200, description = "File contents", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/snapshots/{id}/dump")]
+async fn dump(
+    id: web::Path<String>,
+    query: web::Query<DumpQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(&id) {
+        return Ok(response);
+    }
+    if query.path.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": "File path is required" })));
+    }
+    if query.path.starts_with('-') {
+        return Ok(
+            HttpResponse::BadRequest().json(json!({ "error": "File path must not start with -" }))
+        );
+    }
+
+    let config = data.current_config().await;
+    // the path goes after "--" so a leading dash isn't read as a flag
+    let mut process = spawn_restic(&config, &["dump", &id, "--", &query.path]).await?;
+    let child = &mut process.child;
+
+    let (Some(stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err("Failed to capture restic output".into());
+    };
+
+    // stderr is drained in the background so a chatty restic can't block on a full pipe
+    let stderr = actix_web::rt::spawn(async move {
+        let mut message = String::new();
+        let _ = stderr.read_to_string(&mut message).await;
+        message
+    });
+
+    let mut state = DumpStream {
+        chunks: ReaderStream::new(stdout),
+        process,
+        stderr: Some(stderr),
+        finished: false,
+    };
+
+    // restic writes nothing to stdout when the dump fails, so wait for the first chunk
+    // before committing to a successful response
+    let first = match state.chunks.next().await {
+        Some(Ok(chunk)) => Some(chunk),
+        Some(Err(e)) => return Err(format!("Failed to read restic output: {}", e).into()),
+        None => match state.exit_error().await {
+            Some(err) => return Err(err),
+            None => None,
+        },
+    };
+
+    let filename = query
+        .path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("dump")
+        .to_string();
+
+    // the stream owns the process so restic lives until the body is sent, an empty file
+    // has no first chunk and restic has already exited successfully
+    state.finished = first.is_none();
+    let body = stream::iter(first.map(Ok)).chain(stream::unfold(state, next_chunk));
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header(ContentDisposition::attachment(filename))
+        .streaming(body))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(dump);
+}
@@ -0,0 +1,56 @@
+use crate::content_type::JSON_CONTENT_TYPE;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+
+// 64-bit fnv-1a hash, stable across builds so etags survive restarts
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// whether an If-None-Match header matches the etag, weak comparison as required for GET
+fn matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    let Ok(value) = if_none_match.to_str() else {
+        return false;
+    };
+    value
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+// serializes the value as json with an etag over the body, answering with a 304 when the
+// client already has the same body
+pub fn json_with_etag(req: &HttpRequest, value: &impl Serialize) -> HttpResponse {
+    let body = match serde_json::to_vec(value) {
+        Ok(body) => body,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({ "error": format!("Failed to serialize response: {}", e) }))
+        }
+    };
+    let etag = format!("\"{:016x}\"", fnv1a(&body));
+
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| matches(value, &etag));
+    // no-cache lets clients keep the response but makes them revalidate it every time
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, "no-cache"));
+
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(JSON_CONTENT_TYPE).body(body)
+    }
+}
@@ -0,0 +1,95 @@
+use crate::restic::{run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::IntoParams;
+
+// query parameters for the find endpoint
+#[derive(Deserialize, IntoParams)]
+struct FindQuery {
+    pattern: String,
+    snapshot: Option<String>,
+}
+
+// collects restic's per-snapshot match groups, restic prints them either as one
+// json array or as separate objects depending on the version
+fn group_matches(stdout: &str) -> Result<Vec<Value>, ResticError> {
+    let mut groups = Vec::new();
+
+    for value in serde_json::Deserializer::from_str(stdout).into_iter::<Value>() {
+        let value = value.map_err(|e| format!("Failed to parse JSON: {}", e))?;
+        let found = match value {
+            Value::Array(found) => found,
+            value => vec![value],
+        };
+
+        for group in found {
+            let matches = match &group["matches"] {
+                Value::Array(matches) => matches.clone(),
+                _ => Vec::new(),
+            };
+            groups.push(json!({
+                "snapshot": group["snapshot"],
+                "hits": group["hits"].as_u64().unwrap_or(matches.len() as u64),
+                "matches": matches,
+            }));
+        }
+    }
+    Ok(groups)
+}
+
+// function to find files matching a pattern, optionally in a single snapshot
+async fn find_restic_files(
+    config: &Config,
+    pattern: &str,
+    snapshot: Option<&str>,
+) -> Result<Vec<Value>, ResticError> {
+    let mut args = vec!["find", "--json"];
+    if let Some(snapshot) = snapshot {
+        args.extend(["--snapshot", snapshot]);
+    }
+    // the pattern goes after "--" so a leading dash isn't read as a flag
+    args.extend(["--", pattern]);
+
+    let output = run_restic(config, &args).await?;
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+    group_matches(&stdout)
+}
+
+// endpoint to locate files across snapshots (/find?pattern=*.conf)
+#[utoipa::path(
+    tag = "snapshots",
+    params(FindQuery),
+    responses(
+        (status = 200, description = "Matches grouped by snapshot", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/find")]
+async fn find(
+    query: web::Query<FindQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ResticError> {
+    if query.pattern.trim().is_empty() {
+        return Ok(
+            HttpResponse::BadRequest().json(json!({ "error": "Search pattern is required" }))
+        );
+    }
+
+    let config = data.current_config().await;
+
+    let groups = find_restic_files(
+        &config,
+        &query.pattern,
+        query.snapshot.as_deref().filter(|id| !id.is_empty()),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(groups))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(find);
+}
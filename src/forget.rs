@@ -0,0 +1,335 @@
+use crate::jobs::{accepted, spawn_job, JobQuery};
+use crate::lock::lock_writes;
+use crate::restic::{run_restic, snapshot_not_found, validate_snapshot_id, ResticError};
+use crate::snapshot::GROUP_BY_FIELDS;
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+// request structure for the forget endpoint, only the given retention flags are passed to restic
+#[derive(Deserialize, ToSchema)]
+struct ForgetRequest {
+    keep_last: Option<u32>,
+    keep_hourly: Option<u32>,
+    keep_daily: Option<u32>,
+    keep_weekly: Option<u32>,
+    keep_monthly: Option<u32>,
+    keep_yearly: Option<u32>,
+    #[serde(default)]
+    prune: bool,
+    // report what would be removed without removing anything
+    #[serde(default)]
+    dry_run: bool,
+    // only apply the policy to snapshots of this host
+    host: Option<String>,
+    // only apply the policy to snapshots with these tags, each one is passed as its own
+    // --tag so snapshots with any of them match
+    #[serde(default)]
+    tags: Vec<String>,
+    // comma separated fields the policy is applied per group of, restic groups by host and
+    // paths by default and an empty string applies it to all snapshots together
+    group_by: Option<String>,
+}
+
+impl ForgetRequest {
+    // pairs each retention flag with its requested value
+    fn policy(&self) -> [(&'static str, Option<u32>); 6] {
+        [
+            ("--keep-last", self.keep_last),
+            ("--keep-hourly", self.keep_hourly),
+            ("--keep-daily", self.keep_daily),
+            ("--keep-weekly", self.keep_weekly),
+            ("--keep-monthly", self.keep_monthly),
+            ("--keep-yearly", self.keep_yearly),
+        ]
+    }
+
+    // checks whether at least one retention flag is set
+    fn has_policy(&self) -> bool {
+        self.policy().iter().any(|(_, value)| value.is_some())
+    }
+
+    // checks the host, tag and group filters, an error names the invalid one
+    fn validate_filters(&self) -> Result<(), String> {
+        if self
+            .host
+            .as_deref()
+            .is_some_and(|host| host.trim().is_empty())
+        {
+            return Err("host must not be empty".to_string());
+        }
+        if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+            return Err("tags must not be empty".to_string());
+        }
+        let fields = self.group_by.as_deref().unwrap_or_default();
+        match fields
+            .split(',')
+            .find(|field| !field.is_empty() && !GROUP_BY_FIELDS.contains(field))
+        {
+            Some(field) => Err(format!(
+                "Unknown group_by field \"{}\", expected {}",
+                field,
+                GROUP_BY_FIELDS.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // builds the restic forget arguments from the retention policy and filters
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["forget".to_string(), "--json".to_string()];
+
+        for (flag, value) in self.policy() {
+            if let Some(value) = value {
+                args.push(flag.to_string());
+                args.push(value.to_string());
+            }
+        }
+
+        if let Some(host) = &self.host {
+            args.push("--host".to_string());
+            args.push(host.clone());
+        }
+        for tag in &self.tags {
+            args.push("--tag".to_string());
+            args.push(tag.clone());
+        }
+        if let Some(group_by) = &self.group_by {
+            args.push("--group-by".to_string());
+            args.push(group_by.clone());
+        }
+
+        if self.prune {
+            args.push("--prune".to_string());
+        }
+        if self.dry_run {
+            args.push("--dry-run".to_string());
+        }
+        args
+    }
+}
+
+// function to apply a retention policy using restic forget
+async fn forget_restic_snapshots(
+    config: &Config,
+    req: &ForgetRequest,
+) -> Result<Value, ResticError> {
+    let args = req.args();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let output = run_restic(config, &args).await?;
+
+    // the json report comes first, prune may print plain text after it
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+    serde_json::Deserializer::from_str(&stdout)
+        .into_iter::<Value>()
+        .next()
+        .ok_or("Restic did not report a forget result")?
+        .map_err(|e| format!("Failed to parse JSON: {}", e).into())
+}
+
+// request structure for the bulk forget endpoint
+#[derive(Deserialize, ToSchema)]
+struct ForgetBulkRequest {
+    snapshot_ids: Vec<String>,
+}
+
+// whether restic reported that no snapshot matched the id, it names the id in quotes
+fn id_not_found(stderr: &str, id: &str) -> bool {
+    let quoted = format!("\"{}\"", id);
+    stderr
+        .lines()
+        .any(|line| snapshot_not_found(line) && line.contains(&quoted))
+}
+
+// forgets all snapshots with a single restic forget --prune, so the repository is only
+// pruned once, and reports for each id whether it was deleted or not found
+async fn forget_restic_snapshot_ids(
+    config: &Config,
+    snapshot_ids: &[String],
+) -> Result<Vec<Value>, ResticError> {
+    let mut args = vec!["forget"];
+    args.extend(snapshot_ids.iter().map(String::as_str));
+    args.push("--prune");
+
+    // depending on the version restic only warns about unknown ids and forgets the
+    // others, or fails with a non-zero exit code after forgetting them
+    let stderr = match run_restic(config, &args).await {
+        Ok(output) => String::from_utf8_lossy(&output.stderr).into_owned(),
+        Err(err) if err.is_snapshot_not_found() => err.stderr().unwrap_or_default().to_string(),
+        Err(err) => return Err(err),
+    };
+
+    Ok(snapshot_ids
+        .iter()
+        .map(|id| {
+            let status = if id_not_found(&stderr, id) {
+                "not_found"
+            } else {
+                "deleted"
+            };
+            json!({ "id": id, "status": status })
+        })
+        .collect())
+}
+
+// endpoint to delete several snapshots at once (/snapshots/forget-bulk)
+#[utoipa::path(
+    tag = "snapshots",
+    request_body = ForgetBulkRequest,
+    responses(
+        (status = 200, description = "Result for each snapshot ID", body = serde_json::Value),
+        (status = 400, description = "Missing or invalid snapshot IDs", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/snapshots/forget-bulk")]
+async fn forget_bulk(
+    data: web::Data<AppState>,
+    req: web::Json<ForgetBulkRequest>,
+) -> Result<HttpResponse, ResticError> {
+    // duplicates are dropped, the results keep the order of the request
+    let mut snapshot_ids: Vec<String> = Vec::new();
+    for id in req.into_inner().snapshot_ids {
+        if !snapshot_ids.contains(&id) {
+            snapshot_ids.push(id);
+        }
+    }
+    if snapshot_ids.is_empty() {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "At least one snapshot ID is required" })));
+    }
+    for id in &snapshot_ids {
+        if let Err(response) = validate_snapshot_id(id) {
+            return Ok(response);
+        }
+    }
+
+    let _guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+    let config = data.current_config().await;
+
+    let results = forget_restic_snapshot_ids(&config, &snapshot_ids).await?;
+    let deleted = results
+        .iter()
+        .filter(|result| result["status"] == "deleted")
+        .count();
+    Ok(HttpResponse::Ok().json(json!({ "deleted": deleted, "results": results })))
+}
+
+// endpoint to forget snapshots using a retention policy (/forget)
+#[utoipa::path(
+    tag = "snapshots",
+    request_body = ForgetRequest,
+    params(JobQuery),
+    responses(
+        (status = 200, description = "Kept and removed snapshots", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/forget")]
+async fn forget(
+    data: web::Data<AppState>,
+    req: web::Json<ForgetRequest>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    if !req.has_policy() {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "At least one keep_* retention option is required" })));
+    }
+
+    if let Err(err) = req.validate_filters() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": err })));
+    }
+
+    let guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+
+    if job.background {
+        let config = config.clone();
+        let req = req.into_inner();
+        let job_id = spawn_job(&data, "forget", async move {
+            let _guard = guard;
+            forget_restic_snapshots(&config, &req).await
+        })
+        .await;
+        return Ok(accepted(job_id));
+    }
+
+    let json = forget_restic_snapshots(&config, &req).await?;
+    Ok(HttpResponse::Ok().json(json))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(forget).service(forget_bulk);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: Value) -> ForgetRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn scopes_the_policy_to_a_host_and_tags() {
+        let req = request(json!({
+            "keep_daily": 30,
+            "host": "db1",
+            "tags": ["database", "nightly"],
+            "group_by": "host,tags",
+        }));
+        assert_eq!(
+            req.args(),
+            [
+                "forget",
+                "--json",
+                "--keep-daily",
+                "30",
+                "--host",
+                "db1",
+                "--tag",
+                "database",
+                "--tag",
+                "nightly",
+                "--group-by",
+                "host,tags"
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_an_empty_group_by() {
+        let req = request(json!({ "keep_last": 1, "group_by": "" }));
+        assert!(req.validate_filters().is_ok());
+        assert!(req
+            .args()
+            .ends_with(&["--group-by".to_string(), String::new()]));
+    }
+
+    #[test]
+    fn rejects_unknown_group_by_fields_and_empty_filters() {
+        for body in [
+            json!({ "keep_last": 1, "group_by": "host,time" }),
+            json!({ "keep_last": 1, "host": " " }),
+            json!({ "keep_last": 1, "tags": [""] }),
+        ] {
+            assert!(request(body).validate_filters().is_err());
+        }
+    }
+}
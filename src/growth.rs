@@ -0,0 +1,130 @@
+use crate::restic::{run_restic, ResticError};
+use crate::sizes::lookup_sizes;
+use crate::{AppState, Config};
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+
+// query parameters for the growth endpoint
+#[derive(Deserialize, IntoParams)]
+struct GrowthQuery {
+    // recompute the per-snapshot stats instead of using the cached ones
+    #[serde(default)]
+    refresh: bool,
+}
+
+// the parts of restic's snapshot json the growth series is built from, restic 0.17 and
+// newer store the backup summary with each snapshot
+#[derive(Deserialize)]
+struct SnapshotSummary {
+    id: String,
+    time: String,
+    summary: Option<BackupSummary>,
+}
+
+#[derive(Deserialize)]
+struct BackupSummary {
+    data_added: Option<u64>,
+}
+
+// one point of the growth series
+#[derive(Serialize, ToSchema)]
+struct GrowthPoint {
+    id: String,
+    short_id: String,
+    time: String,
+    // restore size of the snapshot from restic stats
+    total_size: u64,
+    // bytes the backup added to the repository, null for snapshots taken by restic
+    // versions that don't record a summary
+    data_added: Option<u64>,
+    // data_added summed over this and all earlier snapshots
+    cumulative_data_added: u64,
+}
+
+// lists all snapshots with their backup summaries, oldest first
+async fn list_snapshots(config: &Config) -> Result<Vec<SnapshotSummary>, ResticError> {
+    let output = run_restic(config, &["snapshots", "--json"]).await?;
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+    // restic prints null, or nothing, for an empty repository
+    if stdout.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let snapshots: Option<Vec<SnapshotSummary>> =
+        serde_json::from_str(&stdout).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+
+    let mut snapshots = snapshots.unwrap_or_default();
+    snapshots.sort_by_key(|snapshot| OffsetDateTime::parse(&snapshot.time, &Rfc3339).ok());
+    Ok(snapshots)
+}
+
+// builds the growth series, the snapshot sizes come from the shared size cache
+async fn growth_series(
+    config: &Config,
+    sizes: &mut HashMap<String, u64>,
+) -> Result<Vec<GrowthPoint>, ResticError> {
+    let snapshots = list_snapshots(config).await?;
+
+    // forgotten snapshots are dropped from the cache
+    sizes.retain(|id, _| snapshots.iter().any(|snapshot| snapshot.id == *id));
+    let ids: Vec<&str> = snapshots
+        .iter()
+        .map(|snapshot| snapshot.id.as_str())
+        .collect();
+    lookup_sizes(config, sizes, &ids).await?;
+
+    let mut cumulative = 0;
+    let mut series = Vec::with_capacity(snapshots.len());
+    for snapshot in snapshots {
+        let total_size = sizes.get(&snapshot.id).copied().unwrap_or_default();
+        let data_added = snapshot.summary.and_then(|summary| summary.data_added);
+        cumulative += data_added.unwrap_or_default();
+
+        series.push(GrowthPoint {
+            short_id: snapshot.id.chars().take(8).collect(),
+            id: snapshot.id,
+            time: snapshot.time,
+            total_size,
+            data_added,
+            cumulative_data_added: cumulative,
+        });
+    }
+    Ok(series)
+}
+
+// endpoint returning how the repository grew, one point per snapshot sorted by time
+// (/growth), ?refresh=true recomputes the cached per-snapshot stats
+#[utoipa::path(
+    tag = "repository",
+    params(GrowthQuery),
+    responses(
+        (status = 200, description = "Size and added data of every snapshot, oldest first", body = Vec<GrowthPoint>),
+        (status = 403, description = "Wrong repository password", body = crate::ErrorResponse),
+        (status = 423, description = "Repository is locked", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/growth")]
+async fn growth(
+    data: web::Data<AppState>,
+    query: web::Query<GrowthQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    // holding the cache lock makes concurrent requests wait instead of running stats twice
+    let mut sizes = data.size_cache.lock().await;
+    if query.refresh {
+        sizes.clear();
+    }
+
+    let series = growth_series(&config, &mut sizes).await?;
+    Ok(HttpResponse::Ok().json(series))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(growth);
+}
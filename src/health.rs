@@ -0,0 +1,77 @@
+use crate::restic::{restic_version, run_restic};
+use crate::AppState;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::IntoParams;
+
+// query parameters for the health endpoint
+#[derive(Deserialize, IntoParams)]
+struct HealthQuery {
+    #[serde(default)]
+    repo: bool,
+}
+
+// endpoint for liveness probes (/health), pass ?repo=true to also
+// check that the repository can be opened
+#[utoipa::path(
+    tag = "server",
+    params(HealthQuery),
+    responses(
+        (status = 200, description = "Restic is available", body = serde_json::Value),
+        (status = 503, description = "Unavailable", body = serde_json::Value),
+    )
+)]
+#[get("/health")]
+async fn health(data: web::Data<AppState>, query: web::Query<HealthQuery>) -> impl Responder {
+    let binary_path = data.config.lock().await.restic.binary_path().to_string();
+    let version = match restic_version(&binary_path).await {
+        Ok(version) => version,
+        Err(err) => {
+            return HttpResponse::ServiceUnavailable()
+                .json(json!({ "status": "degraded", "error": err.to_string() }))
+        }
+    };
+
+    if query.repo {
+        let config = data.current_config().await;
+
+        // cat config only reads the small repository config file
+        if let Err(err) = run_restic(&config, &["cat", "config", "--no-lock"]).await {
+            return HttpResponse::ServiceUnavailable().json(json!({
+                "status": "degraded",
+                "restic_version": version,
+                "error": err.to_string(),
+            }));
+        }
+    }
+
+    HttpResponse::Ok().json(json!({ "status": "ok", "restic_version": version }))
+}
+
+// endpoint for readiness probes (/ready), lists the latest snapshot so the credentials,
+// the backend and the repository lock are all exercised
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "The repository can be read", body = serde_json::Value),
+        (status = 503, description = "Unavailable", body = serde_json::Value),
+    )
+)]
+#[get("/ready")]
+async fn ready(data: web::Data<AppState>) -> impl Responder {
+    let config = data.current_config().await;
+
+    match run_restic(&config, &["snapshots", "--json", "--latest", "1"]).await {
+        Ok(_) => HttpResponse::Ok().json(json!({ "status": "ready" })),
+        Err(err) => HttpResponse::ServiceUnavailable().json(json!({
+            "status": "unavailable",
+            "error": err.to_string(),
+            "restic_exit_code": err.exit_code(),
+        })),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(health).service(ready);
+}
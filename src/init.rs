@@ -0,0 +1,50 @@
+use crate::restic::{parse_json, run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
+use serde_json::json;
+
+// function to initialize the configured repository, returns the new repository id
+async fn init_restic_repository(config: &Config) -> Result<String, ResticError> {
+    let output = run_restic(config, &["init", "--json"]).await?;
+    let json = parse_json(output)?;
+
+    json["id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| "Restic did not report a repository id".into())
+}
+
+// checks whether restic refused to init because the repository already exists
+fn is_already_initialized(err: &str) -> bool {
+    err.contains("already initialized") || err.contains("already exists")
+}
+
+// endpoint to initialize a new repository (/init)
+#[utoipa::path(
+    tag = "repository",
+    responses(
+        (status = 200, description = "Repository initialized", body = serde_json::Value),
+        (status = 409, description = "Repository is already initialized", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/init")]
+async fn init(data: web::Data<AppState>) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    match init_restic_repository(&config).await {
+        Ok(id) => Ok(HttpResponse::Ok().json(json!({
+            "message": "Repository initialized successfully",
+            "id": id,
+        }))),
+        Err(err) if is_already_initialized(&err.to_string()) => {
+            Ok(HttpResponse::Conflict()
+                .json(json!({ "error": "Repository is already initialized" })))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(init);
+}
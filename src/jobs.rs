@@ -0,0 +1,162 @@
+use crate::restic::{with_restic_slots, ResticError};
+use crate::shutdown::OperationGuard;
+use crate::webhook::notify;
+use crate::AppState;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+// default time finished jobs are kept when server.job_retention_secs is unset
+const DEFAULT_JOB_RETENTION_SECS: u64 = 3600;
+
+// query parameter long-running endpoints accept to run as a background job
+#[derive(Deserialize, IntoParams)]
+pub struct JobQuery {
+    #[serde(default, rename = "async")]
+    pub background: bool,
+}
+
+// lifecycle of a background job
+#[derive(Serialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+// state of a background job as returned by /jobs/{id}
+#[derive(Serialize, Clone, ToSchema)]
+pub struct JobState {
+    id: Uuid,
+    operation: String,
+    status: JobStatus,
+    started_at: String,
+    finished_at: Option<String>,
+    output: Option<Value>,
+    error: Option<String>,
+    // when the job finished, for removing it once the retention period has passed
+    #[serde(skip)]
+    finished: Option<Instant>,
+}
+
+// current time as an rfc3339 timestamp
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+// how long finished jobs are kept, from server.job_retention_secs
+async fn job_retention(data: &AppState) -> Duration {
+    let secs = data.config.lock().await.server.job_retention_secs;
+    Duration::from_secs(secs.unwrap_or(DEFAULT_JOB_RETENTION_SECS))
+}
+
+// removes the finished jobs that are older than the retention period, so their output
+// doesn't stay in memory for as long as the server runs. running jobs are kept
+fn remove_expired_jobs(jobs: &mut HashMap<Uuid, JobState>, retention: Duration) {
+    jobs.retain(|_, job| {
+        job.finished
+            .is_none_or(|finished| finished.elapsed() < retention)
+    });
+}
+
+// runs the operation in the background and records its result in the job table,
+// returns the id clients poll with /jobs/{id}
+pub async fn spawn_job<F>(data: &web::Data<AppState>, operation: &str, task: F) -> Uuid
+where
+    F: Future<Output = Result<Value, ResticError>> + 'static,
+{
+    let id = Uuid::new_v4();
+    let retention = job_retention(data).await;
+    let mut jobs = data.jobs.lock().await;
+    remove_expired_jobs(&mut jobs, retention);
+    jobs.insert(
+        id,
+        JobState {
+            id,
+            operation: operation.to_string(),
+            status: JobStatus::Running,
+            started_at: now(),
+            finished_at: None,
+            output: None,
+            error: None,
+            finished: None,
+        },
+    );
+    drop(jobs);
+    tracing::info!(job_id = %id, operation, "started background job");
+
+    let data = data.clone();
+    let operation = operation.to_string();
+    actix_web::rt::spawn(async move {
+        // jobs count as active operations so shutdown waits for them too
+        let started = Instant::now();
+        let result = {
+            let _guard = OperationGuard::new(&data.active_operations);
+            with_restic_slots(data.restic_slots.clone(), task).await
+        };
+
+        let webhook_url = data.config.lock().await.server.webhook_url.clone();
+        let error = result.as_ref().err().map(|err| err.to_string());
+        notify(webhook_url, &operation, started.elapsed(), error);
+
+        let retention = job_retention(&data).await;
+        let mut jobs = data.jobs.lock().await;
+        remove_expired_jobs(&mut jobs, retention);
+        if let Some(job) = jobs.get_mut(&id) {
+            job.finished_at = Some(now());
+            job.finished = Some(Instant::now());
+            match result {
+                Ok(output) => {
+                    job.status = JobStatus::Succeeded;
+                    job.output = Some(output);
+                }
+                Err(err) => {
+                    tracing::error!(job_id = %id, error = %err, "background job failed");
+                    job.status = JobStatus::Failed;
+                    job.error = Some(err.to_string());
+                }
+            }
+        }
+    });
+
+    id
+}
+
+// response for an endpoint that started a background job
+pub fn accepted(job_id: Uuid) -> HttpResponse {
+    HttpResponse::Accepted().json(json!({
+        "job_id": job_id,
+        "status_url": format!("/jobs/{}", job_id),
+    }))
+}
+
+// endpoint to poll the state of a background job (/jobs/{id})
+#[utoipa::path(
+    tag = "jobs",
+    params(("id" = Uuid, Path, description = "Job ID")),
+    responses(
+        (status = 200, description = "State of the job", body = JobState),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+    )
+)]
+#[get("/jobs/{id}")]
+async fn job_status(id: web::Path<Uuid>, data: web::Data<AppState>) -> impl Responder {
+    match data.jobs.lock().await.get(&id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(json!({ "error": "Job not found" })),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(job_status);
+}
@@ -0,0 +1,210 @@
+use crate::content_type::JSON_CONTENT_TYPE;
+use crate::restic::{ResticError, ResticProcess};
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use futures_util::{stream, StreamExt};
+use serde_json::json;
+use tokio::io::AsyncReadExt;
+use tokio::process::ChildStdout;
+use tokio::task::JoinHandle;
+use tokio_util::io::ReaderStream;
+
+// checks the structure of a json array as it streams past without building it in memory,
+// brackets have to match and strings have to be closed. memory is bounded by the nesting
+// depth, the values themselves are left to restic
+#[derive(Default)]
+struct JsonChecker {
+    // the brackets of the values currently open
+    open: Vec<u8>,
+    in_string: bool,
+    escaped: bool,
+    // the outer array has been closed, only whitespace may follow
+    complete: bool,
+}
+
+impl JsonChecker {
+    // checks the next bytes of the document
+    fn feed(&mut self, bytes: &[u8]) -> Result<(), String> {
+        for &byte in bytes {
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            if byte.is_ascii_whitespace() {
+                continue;
+            }
+            if self.complete {
+                return Err("Restic printed data after the snapshot list".to_string());
+            }
+            if self.open.is_empty() && byte != b'[' {
+                return Err("Restic didn't print a snapshot list".to_string());
+            }
+
+            match byte {
+                b'[' | b'{' => self.open.push(byte),
+                b']' | b'}' => {
+                    let expected = if byte == b']' { b'[' } else { b'{' };
+                    if self.open.pop() != Some(expected) {
+                        return Err("Restic printed malformed JSON".to_string());
+                    }
+                    self.complete = self.open.is_empty();
+                }
+                b'"' => self.in_string = true,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // checks that the document ended with the outer array closed
+    fn finish(&self) -> Result<(), String> {
+        if self.complete {
+            Ok(())
+        } else {
+            Err("Restic's snapshot list ended early".to_string())
+        }
+    }
+}
+
+// state carried between the chunks of a streamed json response
+struct JsonStream {
+    chunks: ReaderStream<ChildStdout>,
+    process: ResticProcess,
+    stderr: Option<JoinHandle<String>>,
+    checker: JsonChecker,
+    finished: bool,
+}
+
+impl JsonStream {
+    // waits for restic to exit and returns its failure, if it failed
+    async fn exit_error(&mut self) -> Option<ResticError> {
+        let exit_code = match self.process.child.wait().await {
+            Ok(status) if status.success() => return None,
+            Ok(status) => status.code(),
+            Err(_) => None,
+        };
+        let stderr = match self.stderr.take() {
+            Some(handle) => handle.await.unwrap_or_default(),
+            None => String::new(),
+        };
+        Some(ResticError::failed(exit_code, &stderr))
+    }
+}
+
+// reads the next chunk of restic's output, a malformed document or a failed restic ends the
+// stream with an error so the client sees an aborted response instead of truncated json
+async fn next_chunk(
+    mut state: JsonStream,
+) -> Option<(Result<Bytes, actix_web::Error>, JsonStream)> {
+    if state.finished {
+        return None;
+    }
+
+    let err = match state.chunks.next().await {
+        Some(Ok(chunk)) => match state.checker.feed(&chunk) {
+            Ok(()) => return Some((Ok(chunk), state)),
+            Err(e) => ResticError::from(e),
+        },
+        Some(Err(e)) => ResticError::from(format!("Failed to read restic output: {}", e)),
+        None => match state.exit_error().await {
+            Some(err) => err,
+            None => match state.checker.finish() {
+                Ok(()) => return None,
+                Err(e) => ResticError::from(e),
+            },
+        },
+    };
+
+    state.finished = true;
+    tracing::warn!(error = %err, "aborting streamed response");
+    Some((Err(err.into()), state))
+}
+
+// streams the json array a restic process prints straight into the response, restic's
+// failure before it printed anything is returned as the error response. restic prints
+// null, or nothing, for an empty repository, which is answered with []
+pub async fn stream_json_array(mut process: ResticProcess) -> Result<HttpResponse, ResticError> {
+    let child = &mut process.child;
+    let (Some(stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err("Failed to capture restic output".into());
+    };
+
+    // stderr is drained in the background so a chatty restic can't block on a full pipe
+    let stderr = actix_web::rt::spawn(async move {
+        let mut message = String::new();
+        let _ = stderr.read_to_string(&mut message).await;
+        message
+    });
+
+    let mut state = JsonStream {
+        chunks: ReaderStream::new(stdout),
+        process,
+        stderr: Some(stderr),
+        checker: JsonChecker::default(),
+        finished: false,
+    };
+
+    // the first chunk decides between an error response and a successful stream
+    let first = loop {
+        match state.chunks.next().await {
+            Some(Ok(chunk)) if chunk.trim_ascii().is_empty() => continue,
+            Some(Ok(chunk)) => break Some(chunk),
+            Some(Err(e)) => return Err(format!("Failed to read restic output: {}", e).into()),
+            None => break None,
+        }
+    };
+    let first = match first {
+        Some(chunk) if chunk.trim_ascii() != b"null" => chunk,
+        _ => {
+            if let Some(err) = state.exit_error().await {
+                return Err(err);
+            }
+            return Ok(HttpResponse::Ok().json(json!([])));
+        }
+    };
+    state.checker.feed(&first)?;
+
+    let body = stream::once(async { Ok(first) }).chain(stream::unfold(state, next_chunk));
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_CONTENT_TYPE)
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(chunks: &[&str]) -> Result<(), String> {
+        let mut checker = JsonChecker::default();
+        for chunk in chunks {
+            checker.feed(chunk.as_bytes())?;
+        }
+        checker.finish()
+    }
+
+    #[test]
+    fn accepts_arrays_split_across_chunks() {
+        assert_eq!(
+            check(&[
+                "[{\"id\":\"a\",\"paths\":[\"/ho",
+                "me\"]},{\"tags\":[\"x]\\\"\"]}]\n"
+            ]),
+            Ok(())
+        );
+        assert_eq!(check(&["[]"]), Ok(()));
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(check(&["{\"id\":\"a\"}"]).is_err());
+        assert!(check(&["[{\"id\":\"a\"]}"]).is_err());
+        assert!(check(&["[{\"id\":\"a\"}"]).is_err());
+        assert!(check(&["[\"unterminated]"]).is_err());
+        assert!(check(&["[] []"]).is_err());
+    }
+}
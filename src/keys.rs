@@ -0,0 +1,133 @@
+use crate::restic::{parse_json, run_restic, run_restic_with_input, ResticError};
+use crate::{AppState, Config};
+use actix_web::{delete, get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+// request structure for adding a key
+#[derive(Deserialize, ToSchema)]
+struct AddKeyRequest {
+    password: String,
+}
+
+// function to list the repository keys using restic key list
+async fn list_restic_keys(config: &Config) -> Result<Vec<Value>, ResticError> {
+    let output = run_restic(config, &["key", "list", "--json"]).await?;
+    match parse_json(output)? {
+        Value::Array(keys) => Ok(keys),
+        _ => Err("Unexpected restic key list output".into()),
+    }
+}
+
+// function to add a key, restic reads the new password from stdin when it isn't a terminal,
+// so the password is neither passed as an argument nor written to disk
+async fn add_restic_key(config: &Config, password: &str) -> Result<Option<String>, ResticError> {
+    let input = format!("{}\n", password);
+    let output = run_restic_with_input(config, &["key", "add"], Some(&input)).await?;
+
+    // restic confirms with "saved new key with ID <id>"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .find(|line| line.starts_with("saved new key"))
+        .and_then(|line| line.split_whitespace().last())
+        .map(str::to_string))
+}
+
+// endpoint to list the repository keys (/keys)
+#[utoipa::path(
+    tag = "keys",
+    responses(
+        (status = 200, description = "Repository keys", body = Vec<serde_json::Value>),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/keys")]
+async fn list_keys(data: web::Data<AppState>) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let keys = list_restic_keys(&config).await?;
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+// endpoint to add a key with a new password (/keys)
+#[utoipa::path(
+    tag = "keys",
+    request_body = AddKeyRequest,
+    responses(
+        (status = 200, description = "ID of the new key", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/keys")]
+async fn add_key(
+    data: web::Data<AppState>,
+    req: web::Json<AddKeyRequest>,
+) -> Result<HttpResponse, ResticError> {
+    if req.password.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": "Password is required" })));
+    }
+    if req.password.contains('\n') {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "Password must not contain line breaks" })));
+    }
+
+    let config = data.current_config().await;
+
+    let id = add_restic_key(&config, &req.password).await?;
+    Ok(HttpResponse::Ok().json(json!({ "message": "Key added successfully", "id": id })))
+}
+
+// endpoint to remove a key by its id (/keys/{id}), the last remaining key and the key
+// the api itself uses are never removed
+#[utoipa::path(
+    tag = "keys",
+    params(("id" = String, Path, description = "Key ID")),
+    responses(
+        (status = 200, description = "Key removed", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[delete("/keys/{id}")]
+async fn remove_key(
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ResticError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": "Invalid key ID" })));
+    }
+
+    let config = data.current_config().await;
+
+    let keys = list_restic_keys(&config).await?;
+
+    // restic lists shortened ids, so either id may be a prefix of the other
+    let Some(key) = keys.iter().find(|key| {
+        key["id"]
+            .as_str()
+            .is_some_and(|key_id| key_id.starts_with(id.as_str()) || id.starts_with(key_id))
+    }) else {
+        return Ok(HttpResponse::NotFound().json(json!({ "error": "Key not found" })));
+    };
+
+    if keys.len() <= 1 {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "Can't remove the last remaining key" })));
+    }
+    if key["current"] == true {
+        return Ok(HttpResponse::BadRequest().json(
+            json!({ "error": "Can't remove the key the API uses to access the repository" }),
+        ));
+    }
+
+    run_restic(&config, &["key", "remove", &id]).await?;
+    Ok(HttpResponse::Ok().json(json!({ "message": "Key removed successfully" })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_keys).service(add_key).service(remove_key);
+}
@@ -0,0 +1,16 @@
+use crate::AppState;
+use actix_web::HttpResponse;
+use serde_json::json;
+use tokio::sync::OwnedMutexGuard;
+
+// held while a write operation runs, dropping it lets the next write operation start
+pub type WriteGuard = OwnedMutexGuard<()>;
+
+// takes the repository write lock without waiting, restic refuses overlapping writes on
+// the same repository so a second write operation is answered with a 409 instead
+pub fn lock_writes(data: &AppState) -> Result<WriteGuard, HttpResponse> {
+    data.write_lock.clone().try_lock_owned().map_err(|_| {
+        HttpResponse::Conflict()
+            .json(json!({ "error": "Another write operation is already in progress" }))
+    })
+}
@@ -0,0 +1,151 @@
+use crate::restic::{run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{get, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// hint returned with a 423 so clients know how to get rid of a stale lock
+pub const UNLOCK_HINT: &str =
+    "If the process holding the lock is no longer running, remove the stale lock with POST /unlock";
+
+// the lock another process holds on the repository, as far as restic reported it
+#[derive(Serialize, ToSchema, Debug, PartialEq)]
+pub struct LockInfo {
+    pub pid: Option<u32>,
+    pub hostname: Option<String>,
+    pub username: Option<String>,
+    // local time of the host holding the lock, like "2024-01-01 10:00:00", or an rfc3339
+    // timestamp when read from the lock file
+    pub created_at: Option<String>,
+    pub exclusive: bool,
+    // id of the lock file in the repository, shortened in restic's lock errors
+    pub lock_id: Option<String>,
+}
+
+// the lock file as printed by restic cat lock
+#[derive(Deserialize)]
+struct LockFile {
+    time: Option<String>,
+    #[serde(default)]
+    exclusive: bool,
+    hostname: Option<String>,
+    username: Option<String>,
+    pid: Option<u32>,
+}
+
+// returns the text following marker up to the first occurrence of end
+fn between<'a>(text: &'a str, marker: &str, end: &str) -> Option<&'a str> {
+    let start = text.find(marker)? + marker.len();
+    let rest = &text[start..];
+    Some(rest.find(end).map_or(rest, |i| &rest[..i]).trim())
+}
+
+// reads the lock holder from restic's "repository is already locked" error, which looks like
+//   repository is already locked exclusively by PID 42 on host by root (UID 0, GID 0)
+//   lock was created at 2024-01-01 10:00:00 (5m0s ago)
+//   storage ID 9f8e7d6c
+// None when stderr doesn't describe the lock
+pub fn parse_lock_error(stderr: &str) -> Option<LockInfo> {
+    let holder = between(stderr, "by PID ", "\n")?;
+    let (pid, holder) = holder.split_once(" on ")?;
+    let (hostname, owner) = holder.split_once(" by ")?;
+    let username = owner.split(" (UID").next().unwrap_or(owner);
+
+    Some(LockInfo {
+        pid: pid.parse().ok(),
+        hostname: Some(hostname.to_string()),
+        username: Some(username.to_string()).filter(|username| !username.is_empty()),
+        created_at: between(stderr, "lock was created at ", " (").map(str::to_string),
+        exclusive: stderr.contains("locked exclusively by"),
+        lock_id: between(stderr, "storage ID ", "\n").map(str::to_string),
+    })
+}
+
+// lists the ids of the lock files in the repository, without locking it since the
+// listing would otherwise fail on an exclusively locked repository
+pub async fn list_lock_ids(config: &Config) -> Result<Vec<String>, ResticError> {
+    let output = run_restic(config, &["list", "locks", "--no-lock"]).await?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+// reads the holder of every lock in the repository, a lock released between the listing
+// and reading it is left out
+async fn read_locks(config: &Config) -> Result<Vec<LockInfo>, ResticError> {
+    let mut locks = Vec::new();
+    for id in list_lock_ids(config).await? {
+        let output = match run_restic(config, &["cat", "lock", &id, "--no-lock"]).await {
+            Ok(output) => output,
+            Err(ResticError::Failed(_) | ResticError::NotFound(_)) => continue,
+            Err(err) => return Err(err),
+        };
+        let lock: LockFile = serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("Failed to parse lock {}: {}", id, e))?;
+
+        locks.push(LockInfo {
+            pid: lock.pid,
+            hostname: lock.hostname,
+            username: lock.username,
+            created_at: lock.time,
+            exclusive: lock.exclusive,
+            lock_id: Some(id),
+        });
+    }
+    Ok(locks)
+}
+
+// endpoint listing the locks held on the repository and who holds them (/locks), to see
+// what POST /unlock would remove
+#[utoipa::path(
+    tag = "repository",
+    responses(
+        (status = 200, description = "The locks in the repository, empty when it isn't locked", body = Vec<LockInfo>),
+        (status = 403, description = "Wrong repository password", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/locks")]
+async fn list_locks(data: web::Data<AppState>) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    Ok(HttpResponse::Ok().json(read_locks(&config).await?))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_locks);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_shared_lock() {
+        let stderr = "unable to create lock in backend: repository is already locked by PID 7 \
+                      on laptop by alice (UID 1000, GID 1000)\nlock was created at \
+                      2024-03-01 08:30:00 (2h0m0s ago)\nstorage ID 0a1b2c3d\n";
+        assert_eq!(
+            parse_lock_error(stderr),
+            Some(LockInfo {
+                pid: Some(7),
+                hostname: Some("laptop".to_string()),
+                username: Some("alice".to_string()),
+                created_at: Some("2024-03-01 08:30:00".to_string()),
+                exclusive: false,
+                lock_id: Some("0a1b2c3d".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_errors_without_lock_details() {
+        assert_eq!(
+            parse_lock_error("unable to create lock in backend: repository is already locked"),
+            None
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::restic::{parse_json_lines, run_restic, validate_snapshot_id, ResticError};
+use crate::{AppState, Config};
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use utoipa::IntoParams;
+
+// query parameters for the ls and browse endpoints
+#[derive(Deserialize, IntoParams)]
+struct LsQuery {
+    path: Option<String>,
+    // read without locking the repository, e.g. while a backup is running
+    #[serde(default)]
+    no_lock: bool,
+}
+
+// directory containing a path from restic's listing, "/" for top level entries
+fn parent_dir(path: &str) -> &str {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/",
+        Some((parent, _)) => parent,
+    }
+}
+
+// keeps only the nodes directly inside dir and the fields the browser needs, restic
+// also prints the snapshot itself and, depending on the version, the directory or
+// everything below it
+fn direct_children(entries: Vec<Value>, dir: &str) -> Vec<Value> {
+    entries
+        .into_iter()
+        .filter(|entry| entry["struct_type"] == "node")
+        .filter(|entry| {
+            entry["path"]
+                .as_str()
+                .is_some_and(|path| path != dir && parent_dir(path) == dir)
+        })
+        .map(|entry| {
+            json!({
+                "name": entry["name"],
+                "path": entry["path"],
+                "type": entry["type"],
+                "size": entry["size"],
+                "mtime": entry["mtime"],
+            })
+        })
+        .collect()
+}
+
+// function to list the files in a snapshot, optionally below a single directory
+async fn list_restic_snapshot(
+    config: &Config,
+    snapshot_id: &str,
+    path: Option<&str>,
+    no_lock: bool,
+) -> Result<Vec<Value>, ResticError> {
+    let mut args = vec!["ls", snapshot_id, "--json"];
+    if no_lock {
+        args.push("--no-lock");
+    }
+    // the path goes after "--" so a leading dash isn't read as a flag
+    if let Some(path) = path {
+        args.extend(["--", path]);
+    }
+
+    let output = run_restic(config, &args).await?;
+    parse_json_lines(output)
+}
+
+// endpoint to browse the files in a snapshot (/snapshots/{id}/ls)
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID"), LsQuery),
+    responses(
+        (status = 200, description = "Files in the snapshot", body = Vec<serde_json::Value>),
+        (status = 400, description = "Invalid snapshot ID", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/snapshots/{id}/ls")]
+async fn ls(
+    id: web::Path<String>,
+    query: web::Query<LsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(&id) {
+        return Ok(response);
+    }
+
+    let config = data.current_config().await;
+
+    let entries = list_restic_snapshot(
+        &config,
+        &id,
+        query.path.as_deref().filter(|path| !path.is_empty()),
+        query.no_lock,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+// endpoint listing one directory level of a snapshot (/snapshots/{id}/browse?path=/etc),
+// so clients can expand folders on demand instead of fetching the whole listing
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID or \"latest\""), LsQuery),
+    responses(
+        (status = 200, description = "The directory and its immediate children", body = serde_json::Value),
+        (status = 400, description = "Invalid snapshot ID or path", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/snapshots/{id}/browse")]
+async fn browse(
+    id: web::Path<String>,
+    query: web::Query<LsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(&id) {
+        return Ok(response);
+    }
+
+    let dir = match query.path.as_deref().filter(|path| !path.is_empty()) {
+        None => "/",
+        Some(path) if path.starts_with('/') => match path.trim_end_matches('/') {
+            "" => "/",
+            path => path,
+        },
+        Some(_) => {
+            return Ok(HttpResponse::BadRequest().json(json!({ "error": "Path must be absolute" })))
+        }
+    };
+
+    let config = data.current_config().await;
+
+    // without --recursive restic only lists the directory itself and its direct children,
+    // so the root is passed too instead of listing the whole snapshot
+    match list_restic_snapshot(&config, &id, Some(dir), query.no_lock).await {
+        Ok(entries) => Ok(HttpResponse::Ok().json(json!({
+            "path": dir,
+            "entries": direct_children(entries, dir),
+        }))),
+        Err(err) if err.is_snapshot_not_found() => {
+            Ok(HttpResponse::NotFound().json(json!({ "error": "snapshot not found" })))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(ls).service(browse);
+}
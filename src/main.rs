@@ -1,215 +1,1118 @@
-use actix_cors::Cors;
-use actix_web::{delete, get, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::error::{InternalError, JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::ResponseError;
+use actix_web::{delete, get, web, HttpRequest, HttpResponse, HttpServer};
 use serde::Deserialize;
-use serde_json::{json, Value};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::error::Error;
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::process::Command;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
-use tempfile::NamedTempFile;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore};
+use tracing_subscriber::EnvFilter;
+use utoipa::IntoParams;
+use uuid::Uuid;
+mod access_log;
+mod audit;
+mod auth;
+mod backup;
+mod cache;
+mod cat;
+mod check;
+mod checks;
+mod content_type;
+mod copy;
+mod dashboard;
+mod diff;
+mod dump;
+mod etag;
+mod find;
+mod forget;
+mod growth;
+mod health;
+mod init;
+mod jobs;
+mod json_stream;
+mod keys;
+mod lock;
+mod locks;
+mod ls;
+mod metrics;
+mod middleware;
+mod openapi;
+mod optional_json;
+mod output;
+mod prune;
+mod ratelimit;
+#[cfg(unix)]
+mod reload;
+mod repair;
+mod restic;
 mod restore;
+mod settings;
+mod shutdown;
+mod sizes;
+mod snapshot;
+mod sse;
 mod stats;
-use restore::restore_snapshot;
-use stats::config as stats_config;
+mod tags;
+#[cfg(all(test, unix))]
+mod tests;
+mod tls;
+mod unlock;
+mod version;
+mod webhook;
+use backup::{config as backup_config, BackupProfile};
+use cache::config as cache_config;
+use cat::config as cat_config;
+use check::config as check_config;
+use copy::config as copy_config;
+use dashboard::config as dashboard_config;
+use diff::config as diff_config;
+use dump::config as dump_config;
+use etag::json_with_etag;
+use find::config as find_config;
+use forget::config as forget_config;
+use growth::config as growth_config;
+use health::config as health_config;
+use init::config as init_config;
+use jobs::{config as jobs_config, JobState};
+use json_stream::stream_json_array;
+use keys::config as keys_config;
+use lock::lock_writes;
+use locks::{config as locks_config, LockInfo};
+use ls::config as ls_config;
+use metrics::config as metrics_config;
+use openapi::config as openapi_config;
+use output::{text_response, OutputFormat};
+use prune::config as prune_config;
+use ratelimit::TokenBucket;
+use repair::config as repair_config;
+use restic::{
+    restic_version, run_restic, snapshot_not_found, spawn_restic, validate_snapshot_id, ResticError,
+};
+use restore::{restore_snapshot, restore_snapshot_by_id, restore_stream};
+use settings::config as settings_config;
+use shutdown::{graceful_shutdown, DEFAULT_GRACE_SECS};
+use sizes::attach_sizes;
+use snapshot::{parse_snapshot_groups, parse_snapshots, Snapshot, TimeRange, GROUP_BY_FIELDS};
+use stats::{config as stats_config, StatsCache};
+use tags::config as tags_config;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tls::load_rustls_config;
+use unlock::config as unlock_config;
+use version::config as version_config;
 
 // configuration structure based on the expected structure of config.toml
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 struct Config {
     repository: RepositoryConfig,
     server: ServerConfig,
+    // named repositories snapshots can be copied to
+    #[serde(default)]
+    destinations: HashMap<String, RepositoryConfig>,
+    #[serde(default)]
+    restic: ResticConfig,
+    // named backup profiles run with POST /backup/{profile}
+    #[serde(default)]
+    backups: HashMap<String, BackupProfile>,
 }
 
-// repository configuration details, including the path to the restic repository and password
-#[derive(Deserialize)]
+// settings for running the restic binary itself
+#[derive(Deserialize, Clone, Default)]
+struct ResticConfig {
+    // path to the restic binary, looked up on PATH when it is only a name
+    binary_path: Option<String>,
+    // cpu priority restic runs with through nice, from -20 (highest) to 19 (lowest)
+    nice: Option<i32>,
+    // io scheduling class restic runs with through ionice on linux, "idle" or "best-effort"
+    ionice: Option<String>,
+    // priority within the best-effort class, from 0 (highest) to 7 (lowest)
+    ionice_level: Option<u8>,
+    // default target size of the pack files backups write in MiB, passed as --pack-size
+    pack_size_mb: Option<u32>,
+}
+
+impl ResticConfig {
+    // the configured binary, "restic" when none is set
+    fn binary_path(&self) -> &str {
+        self.binary_path
+            .as_deref()
+            .filter(|path| !path.trim().is_empty())
+            .unwrap_or("restic")
+    }
+
+    // the program and arguments restic is started through, nice and ionice are checked
+    // for with the platform and left out where they are not available
+    fn command_line(&self) -> Vec<String> {
+        let mut command = Vec::new();
+        if let Some(nice) = self.nice.filter(|_| cfg!(unix)) {
+            command.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        }
+        if let Some(class) = self.ionice.as_ref().filter(|_| cfg!(target_os = "linux")) {
+            command.extend(["ionice".to_string(), "-c".to_string(), class.clone()]);
+            if let Some(level) = self.ionice_level {
+                command.extend(["-n".to_string(), level.to_string()]);
+            }
+        }
+        command.push(self.binary_path().to_string());
+        command
+    }
+
+    // priority settings that are configured but ignored on this platform
+    fn unsupported_priority_settings(&self) -> Vec<&'static str> {
+        let mut unsupported = Vec::new();
+        if self.nice.is_some() && !cfg!(unix) {
+            unsupported.push("restic.nice");
+        }
+        if self.ionice.is_some() && !cfg!(target_os = "linux") {
+            unsupported.push("restic.ionice");
+        }
+        unsupported
+    }
+}
+
+// repository configuration details, including the path to the restic repository and password,
+// both fall back to the RESTIC_REPOSITORY and RESTIC_PASSWORD environment variables
+#[derive(Deserialize, Clone)]
 struct RepositoryConfig {
-    path: String,
-    password: String,
+    path: Option<String>,
+    password: Option<String>,
+    // command restic runs to obtain the password when no password is configured
+    password_command: Option<String>,
+    // extra environment variables for restic, e.g. backend credentials
+    #[serde(default)]
+    env: HashMap<String, String>,
+}
+
+impl RepositoryConfig {
+    // effective repository path, always set once the config has been loaded
+    fn path(&self) -> &str {
+        self.path.as_deref().unwrap_or_default()
+    }
+
+    // effective repository password, only empty when a password command is used instead
+    fn password(&self) -> &str {
+        self.password.as_deref().unwrap_or_default()
+    }
+
+    // password command to hand to restic, a configured password takes precedence
+    fn password_command(&self) -> Option<&str> {
+        match self.password {
+            Some(_) => None,
+            None => self.password_command.as_deref(),
+        }
+    }
 }
 
-// server configuration for ip address and port
-#[derive(Deserialize)]
+// credentials accepted in an Authorization: Basic header, in addition to the api key
+#[derive(Deserialize, Clone)]
+struct BasicAuthConfig {
+    username: String,
+    // bcrypt hash of the password, e.g. from htpasswd -nbB
+    password_hash: String,
+}
+
+// server configuration for ip address and port, everything else is optional
+#[derive(Deserialize, Clone)]
 struct ServerConfig {
     ip: String,
     port: u16,
+    // additional addresses to listen on, e.g. "[::1]:8080"
+    #[serde(default)]
+    bind: Vec<String>,
+    // key clients must send in the X-API-Key header
+    api_key: Option<String>,
+    // username and password accepted instead of the api key
+    basic_auth: Option<BasicAuthConfig>,
+    // restic commands running longer than this are killed
+    command_timeout_secs: Option<u64>,
+    // how often read-only restic commands are retried after a transient backend error
+    max_retries: Option<u32>,
+    // delay before the first retry, doubled for every further attempt
+    retry_backoff_ms: Option<u64>,
+    // how many restic processes may run at once, unlimited when unset
+    max_concurrent_restic: Option<usize>,
+    // url that is posted to when a write operation finishes
+    webhook_url: Option<String>,
+    // append-only file recording every request that can modify the repository
+    audit_log_path: Option<String>,
+    // log filter used when RUST_LOG is not set
+    log_level: Option<String>,
+    // "json" for one json object per log line, human-readable text otherwise
+    log_format: Option<String>,
+    // how long /stats results are cached
+    stats_cache_secs: Option<u64>,
+    // how long shutdown waits for in-flight operations
+    shutdown_grace_secs: Option<u64>,
+    // how long finished background jobs can still be polled
+    job_retention_secs: Option<u64>,
+    // directory restores are limited to
+    restore_root: Option<String>,
+    // restores without a target_dir go into a directory named after the snapshot id in here
+    default_restore_dir: Option<String>,
+    // pem files for serving https, both or neither must be set
+    tls_cert_path: Option<String>,
+    tls_key_path: Option<String>,
+    // how often each client may call the destructive endpoints
+    mutations_per_minute: Option<u32>,
+    // origins browsers may call the api from, any origin is allowed when empty or "*"
+    #[serde(default)]
+    cors_allowed_origins: Vec<String>,
+    // compresses responses for clients that accept it, on unless set to false
+    compression: Option<bool>,
 }
 
-// application state containing the configuration, wrapped in an Arc<Mutex> for thread-safe access
+// application state containing the configuration, wrapped in an Arc<Mutex> so a reload
+// can replace it
 struct AppState {
     config: Arc<Mutex<Config>>,
+    // /stats readings keyed by mode and no_lock
+    stats_cache: Mutex<StatsCache>,
+    // restore sizes of snapshots for /growth and ?with_size=true, keyed by snapshot id
+    size_cache: Mutex<HashMap<String, u64>>,
+    active_operations: Arc<AtomicUsize>,
+    jobs: Mutex<HashMap<Uuid, JobState>>,
+    // serializes operations that modify the repository
+    write_lock: Arc<Mutex<()>>,
+    // rate limit buckets for the destructive endpoints, keyed by client
+    rate_limits: Mutex<HashMap<String, TokenBucket>>,
+    // permits for running restic processes, sized by server.max_concurrent_restic
+    restic_slots: Option<Arc<Semaphore>>,
+    restic_version: Option<String>,
 }
 
-// error response structure for json api responses
-#[derive(serde::Serialize)]
-#[allow(dead_code)] // ignore the dead code warning
+impl AppState {
+    // a copy of the current config, handlers work on a copy so the lock is only held for
+    // the clone and requests don't wait for each other's restic commands
+    async fn current_config(&self) -> Config {
+        self.config.lock().await.clone()
+    }
+}
+
+// error response structure for json api responses, the restic fields are null
+// when the error didn't come from a failed restic process
+#[derive(serde::Serialize, utoipa::ToSchema)]
 struct ErrorResponse {
     error: String,
+    restic_exit_code: Option<i32>,
+    stderr: Option<String>,
+    // who holds the lock on a locked repository, only for a 423 when restic reported it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lock: Option<LockInfo>,
+    // what the client can do about the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<&'static str>,
+}
+
+// command line arguments accepted by the server
+struct CliArgs {
+    config_path: Option<PathBuf>,
+    // validate the configuration and exit instead of starting the server
+    check_config: bool,
 }
 
-// retrieves the config file path from the user's home directory
-fn get_config_path() -> Result<PathBuf, String> {
-    let home_dir = env::var("HOME").map_err(|_| "HOME directory not found".to_string())?;
-    let config_path = PathBuf::from(format!("{}/.config/resticapi/config.toml", home_dir));
+// parses the command line, supporting --config <path>, --config=<path> and --check-config
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<CliArgs, String> {
+    let mut cli = CliArgs {
+        config_path: None,
+        check_config: false,
+    };
+
+    while let Some(arg) = args.next() {
+        if arg == "--check-config" {
+            cli.check_config = true;
+        } else if arg == "--config" {
+            let path = args.next().ok_or("--config requires a path")?;
+            cli.config_path = Some(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            cli.config_path = Some(PathBuf::from(path));
+        } else {
+            return Err(format!("Unknown argument: {}", arg));
+        }
+    }
+
+    Ok(cli)
+}
+
+// retrieves the config file path from --config, the RESTICAPI_CONFIG environment variable
+// or the user's home directory, in that order
+fn get_config_path(cli_path: Option<PathBuf>) -> Result<PathBuf, String> {
+    let config_path = match cli_path.or_else(|| env::var_os("RESTICAPI_CONFIG").map(PathBuf::from))
+    {
+        Some(path) => path,
+        None => {
+            let home_dir = env::var("HOME").map_err(|_| "HOME directory not found".to_string())?;
+            PathBuf::from(format!("{}/.config/resticapi/config.toml", home_dir))
+        }
+    };
 
     if config_path.exists() {
         Ok(config_path)
     } else {
-        Err("Configuration file not found at ~/.config/resticapi/config.toml".to_string())
+        Err(format!(
+            "Configuration file not found at {}",
+            config_path.display()
+        ))
     }
 }
 
-// loads configuration data from the toml file and deserializes it into config struct
-fn load_config() -> Result<Config, Box<dyn Error>> {
-    let config_path = match get_config_path() {
-        Ok(path) => path,
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1); // exit if the configuration file is not found
+// deserializes the config file by its extension, .yaml, .yml and .json files are read as
+// yaml and json and everything else as toml
+fn parse_config(path: &Path, contents: &str) -> Result<Config, Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    Ok(match extension.as_deref() {
+        Some("yaml" | "yml") => serde_yaml::from_str(contents)?,
+        Some("json") => serde_json::from_str(contents)?,
+        _ => toml::from_str(contents)?,
+    })
+}
+
+// loads configuration data from the config file and deserializes it into config struct
+fn load_config(cli_path: Option<PathBuf>) -> Result<Config, Box<dyn Error>> {
+    let config_path = get_config_path(cli_path)?;
+
+    let config_contents = fs::read_to_string(&config_path)?;
+    let mut config = parse_config(&config_path, &config_contents)?;
+
+    // resolves the repository settings from the config file or the environment
+    config.repository.path = Some(resolve_setting(
+        config.repository.path.take(),
+        "RESTIC_REPOSITORY",
+        "repository.path",
+    )?);
+    config.repository.password_command = config
+        .repository
+        .password_command
+        .take()
+        .filter(|command| !command.trim().is_empty());
+    config.repository.password = config.repository.password.take().filter(|p| !p.is_empty());
+
+    // the password command replaces the RESTIC_PASSWORD fallback, not a configured password
+    if config.repository.password.is_none() && config.repository.password_command.is_none() {
+        config.repository.password = Some(resolve_setting(
+            None,
+            "RESTIC_PASSWORD",
+            "repository.password",
+        )?);
+    }
+
+    // destinations have no environment fallback, they must be fully configured
+    for (name, destination) in &mut config.destinations {
+        destination.password = destination.password.take().filter(|p| !p.is_empty());
+        if destination.path().is_empty() {
+            return Err(format!("destinations.{}.path is not set", name).into());
         }
-    };
+        if destination.password.is_none() && destination.password_command.is_none() {
+            return Err(
+                format!("destinations.{} needs a password or password_command", name).into(),
+            );
+        }
+    }
+
+    if let Some(format) = config.server.log_format.as_deref() {
+        if format != "json" && format != "text" {
+            return Err(format!(
+                "server.log_format must be \"json\" or \"text\", not \"{}\"",
+                format
+            )
+            .into());
+        }
+    }
+
+    if let Some(url) = config.server.webhook_url.as_deref() {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err("server.webhook_url must be an http:// or https:// url".into());
+        }
+    }
+
+    if let Some(basic_auth) = &config.server.basic_auth {
+        if basic_auth.username.is_empty() || basic_auth.username.contains(':') {
+            return Err(
+                "server.basic_auth.username must be set and may not contain a colon".into(),
+            );
+        }
+        if basic_auth
+            .password_hash
+            .parse::<bcrypt::HashParts>()
+            .is_err()
+        {
+            return Err("server.basic_auth.password_hash must be a bcrypt hash".into());
+        }
+    }
+
+    if config.server.max_concurrent_restic == Some(0) {
+        return Err("server.max_concurrent_restic must be at least 1".into());
+    }
+
+    if config.server.tls_cert_path.is_some() != config.server.tls_key_path.is_some() {
+        return Err("server.tls_cert_path and server.tls_key_path must be set together".into());
+    }
 
-    let config_contents = fs::read_to_string(config_path)?;
-    let config: Config = toml::from_str(&config_contents)?;
     Ok(config)
 }
 
+// returns the configured value, or the environment variable when the value is missing or empty
+fn resolve_setting(value: Option<String>, env_var: &str, name: &str) -> Result<String, String> {
+    value
+        .filter(|value| !value.is_empty())
+        .or_else(|| env::var(env_var).ok().filter(|value| !value.is_empty()))
+        .ok_or_else(|| {
+            format!(
+                "{} is not set in the config file and {} is not set",
+                name, env_var
+            )
+        })
+}
+
+// query parameters for filtering the snapshot list, a comma separated tag list
+// is forwarded as a single --tag value so restic only matches snapshots with all of them.
+// tags_all and tags_any spell out the and and or matching, see tag_filters. limit and
+// offset page through the list server-side
+#[derive(Deserialize, Default, IntoParams)]
+struct SnapshotsQuery {
+    host: Option<String>,
+    path: Option<String>,
+    tag: Option<String>,
+    // comma separated tags a snapshot must all have
+    tags_all: Option<String>,
+    // comma separated tags a snapshot must have at least one of
+    tags_any: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    // comma separated fields from GROUP_BY_FIELDS, only used by /snapshots
+    group_by: Option<String>,
+    // rfc3339 timestamps, filtered here since restic can't filter by time
+    since: Option<String>,
+    until: Option<String>,
+    // "text" for restic's human readable table, only used by /snapshots
+    format: Option<String>,
+    // read without locking the repository, e.g. while a backup is running
+    #[serde(default)]
+    no_lock: bool,
+    // adds size_bytes to every snapshot, only used by /snapshots
+    #[serde(default)]
+    with_size: bool,
+    // passes restic's json through as it is printed instead of parsing it, only used by
+    // /snapshots
+    #[serde(default)]
+    stream: bool,
+}
+
+impl SnapshotsQuery {
+    // the requested time window, or an error for a malformed timestamp
+    fn time_range(&self) -> Result<TimeRange, String> {
+        TimeRange::parse(self.since.as_deref(), self.until.as_deref())
+    }
+
+    // the requested group by fields, or an error naming the unknown one
+    fn group_by(&self) -> Result<Vec<&str>, String> {
+        let Some(group_by) = self.group_by.as_deref().filter(|value| !value.is_empty()) else {
+            return Ok(Vec::new());
+        };
+
+        group_by
+            .split(',')
+            .map(|field| {
+                if GROUP_BY_FIELDS.contains(&field) {
+                    Ok(field)
+                } else {
+                    Err(format!(
+                        "Unknown group_by field \"{}\", expected {}",
+                        field,
+                        GROUP_BY_FIELDS.join(", ")
+                    ))
+                }
+            })
+            .collect()
+    }
+
+    // the values of the --tag flags for tag, tags_all and tags_any. restic matches a
+    // snapshot with all tags of one comma separated value, and any of the values when
+    // --tag is repeated, so tags_all=a,b&tags_any=c,d becomes --tag a,b,c --tag a,b,d
+    fn tag_filters(&self) -> Vec<String> {
+        let split = |value: &Option<String>| -> Vec<String> {
+            value
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let all = [split(&self.tag), split(&self.tags_all)].concat();
+        let any = split(&self.tags_any);
+
+        if any.is_empty() {
+            return if all.is_empty() {
+                Vec::new()
+            } else {
+                vec![all.join(",")]
+            };
+        }
+        any.iter()
+            .map(|tag| {
+                [all.as_slice(), std::slice::from_ref(tag)]
+                    .concat()
+                    .join(",")
+            })
+            .collect()
+    }
+}
+
+// builds the restic snapshots arguments for the given filters and the --tag values from
+// SnapshotsQuery::tag_filters
+fn snapshots_args<'a>(filter: &'a SnapshotsQuery, tags: &'a [String]) -> Vec<&'a str> {
+    let mut args = vec!["snapshots", "--json"];
+
+    let filters = [("--host", &filter.host), ("--path", &filter.path)];
+    for (flag, value) in filters {
+        if let Some(value) = value {
+            args.extend([flag, value.as_str()]);
+        }
+    }
+    for tag in tags {
+        args.extend(["--tag", tag.as_str()]);
+    }
+    if filter.no_lock {
+        args.push("--no-lock");
+    }
+    args
+}
+
 // executes the restic command to retrieve a list of snapshots in json format
-async fn get_restic_snapshots(repo_path: &str, repo_password: &str) -> Result<Value, String> {
-    // creates a temporary file for the password to securely pass it to the cli
-    let mut password_file = NamedTempFile::new()
-        .map_err(|e| format!("Failed to create temp file for password: {}", e))?;
-
-    // write the password to the temporary file
-    password_file
-        .write_all(repo_password.as_bytes())
-        .map_err(|e| format!("Failed to write password to temp file: {}", e))?;
-
-    // run the Restic command
-    let output = Command::new("restic")
-        .arg("-r")
-        .arg(repo_path)
-        .arg("--password-file")
-        .arg(password_file.path())
-        .arg("snapshots")
-        .arg("--json")
-        .output()
-        .map_err(|e| format!("Failed to execute restic: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Restic error: {}", stderr));
-    }
-
-    let stdout =
-        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
-    let json: Value =
-        serde_json::from_str(&stdout).map_err(|e| format!("Failed to parse JSON: {}", e))?;
-    Ok(json)
-}
-
-// deletes a specific snapshot from the restic repository by snapshot id
-async fn delete_restic_snapshot(
-    repo_path: &str,
-    repo_password: &str,
-    snapshot_id: &str,
-) -> Result<(), String> {
-    // creates a temporary file for the password to securely pass it to the cli
-    let mut password_file = NamedTempFile::new()
-        .map_err(|e| format!("Failed to create temp file for password: {}", e))?;
-
-    // write the password to the temporary file
-    password_file
-        .write_all(repo_password.as_bytes())
-        .map_err(|e| format!("Failed to write password to temp file: {}", e))?;
-
-    // executes the Restic command to delete the snapshot and prune the repository
-    let output = Command::new("restic")
-        .arg("-r")
-        .arg(repo_path)
-        .arg("--password-file")
-        .arg(password_file.path())
-        .arg("forget")
-        .arg(snapshot_id)
-        .arg("--prune")
-        .output()
-        .map_err(|e| format!("Failed to execute restic: {}", e))?;
-
-    // checks if the command executed successfully
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Restic error: {}", stderr));
-    }
-
-    Ok(())
+async fn get_restic_snapshots(
+    config: &Config,
+    filter: &SnapshotsQuery,
+) -> Result<Vec<Snapshot>, ResticError> {
+    let tags = filter.tag_filters();
+    let output = run_restic(config, &snapshots_args(filter, &tags)).await?;
+    parse_snapshots(output)
+}
+
+// retrieves the snapshots matching the filters grouped by the given fields
+async fn get_grouped_restic_snapshots(
+    config: &Config,
+    filter: &SnapshotsQuery,
+    group_by: &[&str],
+) -> Result<BTreeMap<String, Vec<Snapshot>>, ResticError> {
+    let group_by_arg = group_by.join(",");
+    let tags = filter.tag_filters();
+    let mut args = snapshots_args(filter, &tags);
+    args.extend(["--group-by", &group_by_arg]);
+
+    let output = run_restic(config, &args).await?;
+    parse_snapshot_groups(output, group_by)
+}
+
+// picks the snapshot with the newest time, snapshots with an unreadable time are skipped
+fn newest_snapshot(list: impl IntoIterator<Item = Snapshot>) -> Option<Snapshot> {
+    list.into_iter()
+        .filter_map(|snapshot| {
+            let time = OffsetDateTime::parse(&snapshot.time, &Rfc3339).ok()?;
+            Some((time, snapshot))
+        })
+        .max_by_key(|(time, _)| *time)
+        .map(|(_, snapshot)| snapshot)
+}
+
+// retrieves the most recent snapshot matching the filters, restic's --latest picks the
+// newest snapshot of every host and path group so the newest of those is returned.
+// with an until bound the newest snapshots may be outside the window, so all are listed
+async fn get_latest_restic_snapshot(
+    config: &Config,
+    filter: &SnapshotsQuery,
+    range: TimeRange,
+) -> Result<Option<Snapshot>, ResticError> {
+    let tags = filter.tag_filters();
+    let mut args = snapshots_args(filter, &tags);
+    if range.until.is_none() {
+        args.extend(["--latest", "1"]);
+    }
+
+    let output = run_restic(config, &args).await?;
+    let list = parse_snapshots(output)?;
+    Ok(newest_snapshot(
+        list.into_iter().filter(|snapshot| range.contains(snapshot)),
+    ))
+}
+
+// slices the snapshot list to the requested page
+fn paginate(list: Vec<Snapshot>, offset: usize, limit: Option<usize>) -> Vec<Snapshot> {
+    list.into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+// deletes a specific snapshot from the restic repository by snapshot id,
+// returns false when no snapshot matches the id
+async fn delete_restic_snapshot(config: &Config, snapshot_id: &str) -> Result<bool, ResticError> {
+    // forgets the snapshot and prunes the repository, depending on the version restic
+    // only warns about an unknown id or fails with a non-zero exit code
+    match run_restic(config, &["forget", snapshot_id, "--prune"]).await {
+        Ok(output) => Ok(!snapshot_not_found(&String::from_utf8_lossy(
+            &output.stderr,
+        ))),
+        Err(err) if err.is_snapshot_not_found() => Ok(false),
+        Err(err) => Err(err),
+    }
 }
 
 // endpoint to retrieve a list of snapshots (/snapshots)
+#[utoipa::path(
+    tag = "snapshots",
+    params(SnapshotsQuery),
+    responses(
+        (status = 200, description = "All snapshots, a page of them as { total, snapshots } with limit or offset, a map from group to snapshots with group_by, or restic's table with format=text", content(
+            (Vec<Snapshot> = "application/json"),
+            (String = "text/plain"),
+        )),
+        (status = 400, description = "Unknown group_by field or format, or malformed since or until", body = crate::ErrorResponse),
+        (status = 403, description = "Wrong repository password", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 423, description = "Repository is locked", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
 #[get("/snapshots")]
-async fn snapshots(data: web::Data<AppState>) -> impl Responder {
-    let config = data.config.lock().await;
+async fn snapshots(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<SnapshotsQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let group_by = match query.group_by() {
+        Ok(group_by) => group_by,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "error": e }))),
+    };
+    let range = match query.time_range() {
+        Ok(range) => range,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "error": e }))),
+    };
+    let format = match OutputFormat::from_request(&req, query.format.as_deref()) {
+        Ok(format) => format,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "error": e }))),
+    };
+
+    // restic's table is passed through as is, so only restic's own filters apply to it
+    let paged = query.limit.is_some() || query.offset.is_some();
+    if format == OutputFormat::Text && (range.is_set() || paged || query.with_size) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "since, until, limit, offset and with_size are only supported for JSON output"
+        })));
+    }
+
+    // streaming skips parsing the list, so nothing that needs the parsed snapshots applies
+    let processed = !group_by.is_empty() || range.is_set() || paged || query.with_size;
+    if query.stream && (format == OutputFormat::Text || processed) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "stream can't be combined with format, group_by, since, until, limit, offset or with_size"
+        })));
+    }
+
+    let config = data.current_config().await;
+
+    if query.stream {
+        let tags = query.tag_filters();
+        let child = spawn_restic(&config, &snapshots_args(&query, &tags)).await?;
+        return stream_json_array(child).await;
+    }
+
+    if format == OutputFormat::Text {
+        let group_by_arg = group_by.join(",");
+        let tags = query.tag_filters();
+        let mut args = format.apply(snapshots_args(&query, &tags));
+        if !group_by.is_empty() {
+            args.extend(["--group-by", &group_by_arg]);
+        }
+        return Ok(text_response(run_restic(&config, &args).await?));
+    }
+
+    if !group_by.is_empty() {
+        let mut groups = get_grouped_restic_snapshots(&config, &query, &group_by).await?;
+        if range.is_set() {
+            groups.retain(|_, list| {
+                list.retain(|snapshot| range.contains(snapshot));
+                !list.is_empty()
+            });
+        }
+        if query.with_size {
+            for list in groups.values_mut() {
+                attach_sizes(&config, &data.size_cache, list).await?;
+            }
+        }
+        return Ok(json_with_etag(&req, &groups));
+    }
 
-    match get_restic_snapshots(&config.repository.path, &config.repository.password).await {
-        Ok(json) => HttpResponse::Ok().json(json),
-        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+    let mut list = get_restic_snapshots(&config, &query).await?;
+    list.retain(|snapshot| range.contains(snapshot));
+    let total = list.len();
+    if paged {
+        list = paginate(list, query.offset.unwrap_or_default(), query.limit);
     }
+    // sizes are only looked up for the snapshots on the requested page
+    if query.with_size {
+        attach_sizes(&config, &data.size_cache, &mut list).await?;
+    }
+
+    // the plain list is kept unless the client asks for a page
+    Ok(if paged {
+        json_with_etag(&req, &json!({ "total": total, "snapshots": list }))
+    } else {
+        json_with_etag(&req, &list)
+    })
+}
+
+// endpoint to retrieve the most recent snapshot (/snapshots/latest), optionally
+// filtered by host and path
+#[utoipa::path(
+    tag = "snapshots",
+    params(SnapshotsQuery),
+    responses(
+        (status = 200, description = "The most recent snapshot", body = Snapshot),
+        (status = 400, description = "Malformed since or until", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/snapshots/latest")]
+async fn latest_snapshot(
+    data: web::Data<AppState>,
+    query: web::Query<SnapshotsQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let range = match query.time_range() {
+        Ok(range) => range,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "error": e }))),
+    };
+
+    let config = data.current_config().await;
+
+    Ok(
+        match get_latest_restic_snapshot(&config, &query, range).await? {
+            Some(snapshot) => HttpResponse::Ok().json(snapshot),
+            None => HttpResponse::NotFound().json(json!({ "error": "No snapshots found" })),
+        },
+    )
 }
 
 // endpoint to delete a snapshot by its id (/snapshots/{id})
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID")),
+    responses(
+        (status = 200, description = "Snapshot deleted", body = serde_json::Value),
+        (status = 400, description = "Invalid snapshot ID", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
 #[delete("/snapshots/{id}")]
-async fn delete_snapshot(id: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
-    let config = data.config.lock().await;
+async fn delete_snapshot(
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(&id) {
+        return Ok(response);
+    }
+    let _guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+    let config = data.current_config().await;
     let snapshot_id = id.into_inner();
 
-    match delete_restic_snapshot(
-        &config.repository.path,
-        &config.repository.password,
-        &snapshot_id,
-    )
-    .await
-    {
-        Ok(_) => HttpResponse::Ok().json(json!({ "message": "Snapshot deleted successfully" })),
-        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+    Ok(if delete_restic_snapshot(&config, &snapshot_id).await? {
+        HttpResponse::Ok().json(json!({ "message": "Snapshot deleted successfully" }))
+    } else {
+        HttpResponse::NotFound().json(json!({ "error": "snapshot not found" }))
+    })
+}
+
+// resolves a host and port to socket addresses, ipv6 literals may be written with or
+// without brackets and host names are looked up
+fn resolve_address(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
     }
+
+    (host, port)
+        .to_socket_addrs()
+        .map(Iterator::collect)
+        .map_err(|e| format!("Invalid server address {}: {}", host, e))
+}
+
+// collects every address the server listens on, server.ip and server.port come first
+fn bind_addresses(server: &ServerConfig) -> Result<Vec<SocketAddr>, String> {
+    let mut addresses = resolve_address(&server.ip, server.port)?;
+
+    for address in &server.bind {
+        match address.parse::<SocketAddr>() {
+            Ok(address) => addresses.push(address),
+            Err(_) => addresses.extend(
+                address
+                    .to_socket_addrs()
+                    .map_err(|e| format!("Invalid server.bind address {}: {}", address, e))?,
+            ),
+        }
+    }
+
+    Ok(addresses)
+}
+
+// answers a json body that can't be read or doesn't match the request structure with
+// the parse problem, e.g. the missing or mistyped field
+fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let details = match &err {
+        // without actix's "Json deserialize error:" prefix
+        JsonPayloadError::Deserialize(e) => e.to_string(),
+        _ => err.to_string(),
+    };
+    let response = HttpResponse::build(err.status_code())
+        .json(json!({ "error": "invalid request body", "details": details }));
+    InternalError::from_response(err, response).into()
+}
+
+// answers query parameters that don't match the query structure as json too, actix
+// would send the parse problem as plain text
+fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let details = match &err {
+        // without actix's "Query deserialize error:" prefix
+        QueryPayloadError::Deserialize(e) => e.to_string(),
+        _ => err.to_string(),
+    };
+    let response = HttpResponse::build(err.status_code())
+        .json(json!({ "error": "invalid query parameters", "details": details }));
+    InternalError::from_response(err, response).into()
+}
+
+// answers a path segment that can't be read, like a job id that isn't a uuid, as json
+fn path_error(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    let PathError::Deserialize(details) = &err else {
+        return err.into();
+    };
+    let response = HttpResponse::build(err.status_code())
+        .json(json!({ "error": "invalid path", "details": details.to_string() }));
+    InternalError::from_response(err, response).into()
+}
+
+// registers every api endpoint, with json body, query and path errors answered by
+// json_error, query_error and path_error
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(json_error))
+        .app_data(web::QueryConfig::default().error_handler(query_error))
+        .app_data(web::PathConfig::default().error_handler(path_error))
+        .configure(stats_config)
+        .configure(backup_config)
+        .configure(health_config)
+        .configure(check_config)
+        .configure(init_config)
+        .configure(forget_config)
+        .configure(growth_config)
+        .configure(prune_config)
+        .configure(ls_config)
+        .configure(cat_config)
+        .configure(dump_config)
+        .configure(metrics_config)
+        .configure(unlock_config)
+        .configure(cache_config)
+        .configure(repair_config)
+        .configure(diff_config)
+        .configure(find_config)
+        .configure(tags_config)
+        .configure(copy_config)
+        .configure(dashboard_config)
+        .configure(locks_config)
+        .configure(jobs_config)
+        .configure(keys_config)
+        .configure(version_config)
+        .configure(openapi_config)
+        .configure(settings_config)
+        .service(snapshots)
+        .service(latest_snapshot)
+        .service(delete_snapshot)
+        .service(restore_snapshot)
+        .service(restore_snapshot_by_id)
+        .service(restore_stream);
 }
 
 // main function to start the actix web server
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let cli = match parse_args(env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: restic-web-api [--config <path>] [--check-config]");
+            process::exit(2);
+        }
+    };
+
     // load configuration from the config file
-    let config = load_config().expect("Failed to load configuration");
+    let config = match load_config(cli.config_path.clone()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load configuration: {}", e);
+            process::exit(1); // exit if the configuration could not be loaded
+        }
+    };
+
+    if cli.check_config {
+        match checks::check_config(&config).await {
+            Ok(version) => {
+                println!("Configuration is valid, using restic {}", version);
+                process::exit(0);
+            }
+            Err(problems) => {
+                for problem in problems {
+                    eprintln!("{}", problem);
+                }
+                process::exit(1);
+            }
+        }
+    }
+
+    // a malformed repository location would only surface on the first request
+    let problems = checks::check_repositories(&config);
+    if !problems.is_empty() {
+        for problem in problems {
+            eprintln!("{}", problem);
+        }
+        process::exit(1);
+    }
+
+    // RUST_LOG takes precedence over the log level from the config file
+    let log_filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(config.server.log_level.as_deref().unwrap_or("info")));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(log_filter);
+    match config.server.log_format.as_deref() {
+        Some("json") => subscriber.json().init(),
+        _ => subscriber.init(),
+    }
+
+    // nice needs a unix system and ionice linux, elsewhere the settings are ignored
+    for setting in config.restic.unsupported_priority_settings() {
+        tracing::warn!(
+            setting,
+            "not supported on this platform, restic runs without it"
+        );
+    }
+
+    // the restic version won't change while the server runs, so it is only detected once
+    let restic_version = match restic_version(config.restic.binary_path()).await {
+        Ok(version) => Some(version),
+        Err(e) if e.is_missing_binary() => {
+            tracing::error!(
+                binary = config.restic.binary_path(),
+                "restic binary not found, every request that runs restic will fail with a 503"
+            );
+            None
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to detect the restic version");
+            None
+        }
+    };
+
     let config = Arc::new(Mutex::new(config));
 
-    // clones ip and port to avoid moving config later
-    let server_ip;
-    let server_port;
+    // copies the server settings to avoid moving config later
+    let addresses;
+    let shutdown_grace;
+    let tls_config;
+    let startup_config;
+    let restic_slots;
     {
         let config_guard = config.lock().await;
-        server_ip = config_guard.server.ip.clone();
-        server_port = config_guard.server.port;
+        addresses = match bind_addresses(&config_guard.server) {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1); // exit if an address can't be parsed or resolved
+            }
+        };
+        // the middleware stack is built from the config the server started with
+        startup_config = config_guard.clone();
+        restic_slots = config_guard
+            .server
+            .max_concurrent_restic
+            .map(|permits| Arc::new(Semaphore::new(permits)));
+        tls_config = match (
+            &config_guard.server.tls_cert_path,
+            &config_guard.server.tls_key_path,
+        ) {
+            (Some(cert_path), Some(key_path)) => match load_rustls_config(cert_path, key_path) {
+                Ok(tls_config) => Some(tls_config),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1); // exit if tls was requested but can't be set up
+                }
+            },
+            _ => None,
+        };
+        shutdown_grace = Duration::from_secs(
+            config_guard
+                .server
+                .shutdown_grace_secs
+                .unwrap_or(DEFAULT_GRACE_SECS),
+        );
     }
 
+    // the state is shared by all workers so caches are not duplicated per thread
+    let state = web::Data::new(AppState {
+        config: Arc::clone(&config),
+        stats_cache: Mutex::new(HashMap::new()),
+        size_cache: Mutex::new(HashMap::new()),
+        active_operations: Arc::new(AtomicUsize::new(0)),
+        jobs: Mutex::new(HashMap::new()),
+        write_lock: Arc::new(Mutex::new(())),
+        rate_limits: Mutex::new(HashMap::new()),
+        restic_slots,
+        restic_version,
+    });
+    let shutdown_state = state.clone();
+
+    #[cfg(unix)]
+    actix_web::rt::spawn(reload::reload_on_sighup(state.clone(), cli.config_path));
+
     // starts the http server
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header();
-
-        App::new()
-            .wrap(cors)
-            .app_data(web::Data::new(AppState {
-                config: Arc::clone(&config),
-            }))
-            .configure(stats_config)
-            .service(snapshots)
-            .service(delete_snapshot)
-            .service(restore_snapshot)
-    })
-    .bind((server_ip, server_port))?
-    .run()
-    .await
+    let mut server = HttpServer::new(move || middleware::app(&startup_config, state.clone()))
+        .disable_signals()
+        .shutdown_timeout(shutdown_grace.as_secs());
+
+    // serves https when a certificate is configured and plain http otherwise
+    for address in &addresses {
+        let bound = match &tls_config {
+            Some(tls_config) => server.bind_rustls_0_23(address, tls_config.clone()),
+            None => server.bind(address),
+        };
+        server = match bound {
+            Ok(server) => server,
+            Err(e) => {
+                eprintln!("Failed to listen on {}: {}", address, e);
+                process::exit(1);
+            }
+        };
+    }
+    let server = server.run();
+
+    // signals are handled here so shutdown can wait for in-flight restic operations
+    actix_web::rt::spawn(graceful_shutdown(
+        server.handle(),
+        shutdown_state,
+        shutdown_grace,
+    ));
+
+    server.await
 }
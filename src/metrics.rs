@@ -0,0 +1,85 @@
+use crate::restic::ResticError;
+use crate::snapshot::Snapshot;
+use crate::stats::get_restic_stats;
+use crate::{get_restic_snapshots, AppState, SnapshotsQuery};
+use actix_web::{get, web, HttpResponse};
+use serde_json::Value;
+use std::fmt::Write;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+// appends a single gauge in the prometheus text exposition format
+fn write_gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+// returns the unix timestamp of the newest snapshot in the list
+fn last_snapshot_timestamp(snapshots: &[Snapshot]) -> Option<i64> {
+    snapshots
+        .iter()
+        .filter_map(|snapshot| OffsetDateTime::parse(&snapshot.time, &Rfc3339).ok())
+        .map(OffsetDateTime::unix_timestamp)
+        .max()
+}
+
+// renders the repository stats and snapshot list as prometheus metrics
+fn render_metrics(stats: &Value, snapshots: &[Snapshot]) -> String {
+    let mut out = String::new();
+
+    write_gauge(
+        &mut out,
+        "restic_total_size_bytes",
+        "Total size of the repository in bytes.",
+        stats["total_size"].as_u64().unwrap_or_default(),
+    );
+    write_gauge(
+        &mut out,
+        "restic_total_file_count",
+        "Total number of files in the repository.",
+        stats["total_file_count"].as_u64().unwrap_or_default(),
+    );
+    write_gauge(
+        &mut out,
+        "restic_snapshot_count",
+        "Number of snapshots in the repository.",
+        snapshots.len(),
+    );
+
+    // left out for an empty repository so alerts on stale backups don't see a bogus value
+    if let Some(timestamp) = last_snapshot_timestamp(snapshots) {
+        write_gauge(
+            &mut out,
+            "restic_last_snapshot_timestamp_seconds",
+            "Unix timestamp of the newest snapshot.",
+            timestamp,
+        );
+    }
+
+    out
+}
+
+// endpoint exposing repository metrics for prometheus (/metrics)
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "Prometheus metrics", content_type = "text/plain", body = String),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/metrics")]
+async fn metrics(data: web::Data<AppState>) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let stats = get_restic_stats(&config, None).await?;
+    let snapshots = get_restic_snapshots(&config, &SnapshotsQuery::default()).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(render_metrics(&stats, &snapshots)))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(metrics);
+}
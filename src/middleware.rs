@@ -0,0 +1,72 @@
+use crate::access_log::access_log;
+use crate::audit::audit_log;
+use crate::auth::require_api_key;
+use crate::content_type::json_charset;
+use crate::ratelimit::limit_mutations;
+use crate::shutdown::track_operation;
+use crate::webhook::notify_webhook;
+use crate::{routes, AppState, Config};
+use actix_cors::Cors;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Compress, Condition};
+use actix_web::{web, App, Error};
+use tracing_actix_web::TracingLogger;
+
+// builds the cors middleware, a configured origin list is matched exactly and may send
+// credentials, which browsers never allow together with a wildcard origin
+fn build_cors(allowed_origins: &[String]) -> Cors {
+    let cors = Cors::default().allow_any_method().allow_any_header();
+
+    if allowed_origins.is_empty() || allowed_origins.iter().any(|origin| origin == "*") {
+        return cors.allow_any_origin();
+    }
+
+    allowed_origins
+        .iter()
+        .fold(cors.supports_credentials(), |cors, origin| {
+            cors.allowed_origin(origin)
+        })
+}
+
+// builds the app with the full middleware stack, innermost first. cors and compression
+// are set up from the config the server started with, the other middleware read the
+// current config on every request so a reload can turn them on and off:
+//   - webhook notifications, only for the write operations
+//   - operation tracking, so shutdown waits for running restic operations
+//   - rate limiting, behind the api key check so rejected requests don't use up tokens
+//   - the api key and basic auth check
+//   - the audit log, in front of the auth check to also record rejected attempts
+//   - the access log, seeing every response the api sends, including rejected ones
+//   - the json charset, added to every json response including the rejected ones
+//   - cors, after the auth check so preflight requests are answered first
+//   - compression, when server.compression isn't turned off
+//   - the tracing logger, opening a span for every request
+pub fn app(
+    config: &Config,
+    state: web::Data<AppState>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let compression = config.server.compression.unwrap_or(true);
+
+    App::new()
+        .wrap(from_fn(notify_webhook))
+        .wrap(from_fn(track_operation))
+        .wrap(from_fn(limit_mutations))
+        .wrap(from_fn(require_api_key))
+        .wrap(from_fn(audit_log))
+        .wrap(from_fn(access_log))
+        .wrap(from_fn(json_charset))
+        .wrap(build_cors(&config.server.cors_allowed_origins))
+        .wrap(Condition::new(compression, Compress::default()))
+        .wrap(TracingLogger::default())
+        .app_data(state)
+        .configure(routes)
+}
@@ -0,0 +1,91 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+// openapi document generated from the handler annotations
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "restic-web-api"),
+    paths(
+        crate::snapshots,
+        crate::latest_snapshot,
+        crate::delete_snapshot,
+        crate::backup::backup,
+        crate::backup::backup_stream,
+        crate::backup::backup_stdin,
+        crate::backup::backup_profile,
+        crate::cache::list_cache,
+        crate::cache::clear_cache,
+        crate::cat::cat,
+        crate::check::check,
+        crate::copy::copy_snapshot,
+        crate::dashboard::dashboard,
+        crate::diff::diff,
+        crate::dump::dump,
+        crate::find::find,
+        crate::forget::forget,
+        crate::forget::forget_bulk,
+        crate::growth::growth,
+        crate::health::health,
+        crate::health::ready,
+        crate::init::init,
+        crate::jobs::job_status,
+        crate::keys::list_keys,
+        crate::keys::add_key,
+        crate::keys::remove_key,
+        crate::locks::list_locks,
+        crate::ls::ls,
+        crate::ls::browse,
+        crate::metrics::metrics,
+        crate::prune::prune,
+        crate::repair::repair,
+        crate::restore::restore_snapshot,
+        crate::restore::restore_snapshot_by_id,
+        crate::restore::restore_stream,
+        crate::settings::get_config,
+        crate::stats::stats,
+        crate::stats::snapshot_stats,
+        crate::tags::add_tags,
+        crate::tags::remove_tags,
+        crate::unlock::unlock,
+        crate::version::version,
+        openapi_document,
+    ),
+    modifiers(&ApiKeyHeader),
+    security(("api_key" = []), ("basic_auth" = []))
+)]
+struct ApiDoc;
+
+// declares the X-API-Key header and basic auth, either one is accepted and servers
+// without credentials configured simply ignore them
+struct ApiKeyHeader;
+
+impl Modify for ApiKeyHeader {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+        components.add_security_scheme(
+            "basic_auth",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)),
+        );
+    }
+}
+
+// endpoint serving the openapi document (/openapi.json)
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "OpenAPI document describing this API", body = serde_json::Value),
+    )
+)]
+#[get("/openapi.json")]
+async fn openapi_document() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(openapi_document);
+}
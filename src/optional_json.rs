@@ -0,0 +1,35 @@
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::web::{self, Bytes};
+use actix_web::{FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use futures_util::{stream, Stream};
+use serde::de::DeserializeOwned;
+use std::pin::Pin;
+
+// a json body that may be left out, an empty body gives the defaults. a body that was sent
+// has to parse like web::Json, its errors go through the json error handler, where
+// Option<web::Json> would have silently run with the defaults instead
+pub struct OptionalJson<T>(pub T);
+
+impl<T: DeserializeOwned + Default + 'static> FromRequest for OptionalJson<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let body = Bytes::from_request(&req, payload);
+        Box::pin(async move {
+            let body = body.await?;
+            if body.is_empty() {
+                return Ok(OptionalJson(T::default()));
+            }
+
+            // the body is read already, so web::Json has to parse it from a replayed payload
+            let replay: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+                Box::pin(stream::once(async move { Ok(body) }));
+            let json = web::Json::<T>::from_request(&req, &mut Payload::from(replay)).await?;
+            Ok(OptionalJson(json.into_inner()))
+        })
+    }
+}
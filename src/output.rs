@@ -0,0 +1,54 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use std::process::Output;
+
+// response format of the endpoints that can pass restic's own text output through
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Text,
+}
+
+impl OutputFormat {
+    // picks the format from ?format=json|text, or from an Accept header that asks for
+    // text/plain but not json. json stays the default, also for browsers and */*
+    pub fn from_request(req: &HttpRequest, format: Option<&str>) -> Result<Self, String> {
+        match format.filter(|format| !format.is_empty()) {
+            Some("json") => return Ok(OutputFormat::Json),
+            Some("text") => return Ok(OutputFormat::Text),
+            Some(format) => {
+                return Err(format!(
+                    "Unknown format \"{}\", expected json or text",
+                    format
+                ))
+            }
+            None => {}
+        }
+
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if accept.contains("text/plain") && !accept.contains("application/json") {
+            Ok(OutputFormat::Text)
+        } else {
+            Ok(OutputFormat::Json)
+        }
+    }
+
+    // drops --json from restic arguments when the text output is wanted
+    pub fn apply<'a>(&self, mut args: Vec<&'a str>) -> Vec<&'a str> {
+        if *self == OutputFormat::Text {
+            args.retain(|arg| *arg != "--json");
+        }
+        args
+    }
+}
+
+// responds with restic's human readable stdout
+pub fn text_response(output: Output) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(output.stdout)
+}
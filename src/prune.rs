@@ -0,0 +1,117 @@
+use crate::jobs::{accepted, spawn_job, JobQuery};
+use crate::lock::lock_writes;
+use crate::optional_json::OptionalJson;
+use crate::restic::{run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use utoipa::ToSchema;
+
+// lines of restic's prune report that make up the reclaimed-space summary
+const SUMMARY_LINES: [(&str, &str); 6] = [
+    ("to repack", "to_repack"),
+    ("this removes", "this_removes"),
+    ("to delete", "to_delete"),
+    ("total prune", "total_prune"),
+    ("remaining", "remaining"),
+    ("unused size after prune", "unused_size_after_prune"),
+];
+
+// request structure for the prune endpoint, the body is optional
+#[derive(Deserialize, Default, ToSchema)]
+struct PruneRequest {
+    max_unused: Option<String>,
+    // report what would be removed without removing anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl PruneRequest {
+    // builds the restic prune arguments
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["prune".to_string()];
+        if let Some(max_unused) = self.max_unused.as_deref().map(str::trim) {
+            if !max_unused.is_empty() {
+                args.push("--max-unused".to_string());
+                args.push(max_unused.to_string());
+            }
+        }
+        if self.dry_run {
+            args.push("--dry-run".to_string());
+        }
+        args
+    }
+}
+
+// picks the summary lines out of restic's plain text prune report
+fn parse_summary(report: &str) -> Value {
+    let mut summary = Map::new();
+    for line in report.lines() {
+        let Some((label, value)) = line.split_once(':') else {
+            continue;
+        };
+        if let Some((_, key)) = SUMMARY_LINES.iter().find(|(name, _)| *name == label.trim()) {
+            summary.insert(key.to_string(), json!(value.trim()));
+        }
+    }
+    Value::Object(summary)
+}
+
+// function to remove unreferenced data using restic prune
+async fn run_restic_prune(config: &Config, req: &PruneRequest) -> Result<Value, ResticError> {
+    let args = req.args();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let output = run_restic(config, &args).await?;
+
+    // restic prune has no json output, so return the summary together with the full report
+    let report = String::from_utf8_lossy(&output.stdout).into_owned();
+    Ok(json!({
+        "summary": parse_summary(&report),
+        "output": report,
+    }))
+}
+
+// endpoint to prune the repository without forgetting snapshots (/prune)
+#[utoipa::path(
+    tag = "repository",
+    request_body(content = Option<PruneRequest>),
+    params(JobQuery),
+    responses(
+        (status = 200, description = "Reclaimed space summary and restic output", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/prune")]
+async fn prune(
+    data: web::Data<AppState>,
+    OptionalJson(req): OptionalJson<PruneRequest>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+
+    if job.background {
+        let config = config.clone();
+        let job_id = spawn_job(&data, "prune", async move {
+            let _guard = guard;
+            run_restic_prune(&config, &req).await
+        })
+        .await;
+        return Ok(accepted(job_id));
+    }
+
+    let json = run_restic_prune(&config, &req).await?;
+    Ok(HttpResponse::Ok().json(json))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(prune);
+}
@@ -0,0 +1,115 @@
+use crate::auth::ClientIdentity;
+use crate::AppState;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+// destructive endpoints that count against server.mutations_per_minute
+const LIMITED_ROUTES: [(Method, &str); 7] = [
+    (Method::DELETE, "/snapshots/{id}"),
+    (Method::POST, "/snapshots/forget-bulk"),
+    (Method::POST, "/restore"),
+    (Method::POST, "/restore/stream"),
+    (Method::POST, "/snapshots/{id}/restore"),
+    (Method::POST, "/forget"),
+    (Method::POST, "/prune"),
+];
+
+// time an empty bucket takes to refill completely
+const REFILL_TIME: Duration = Duration::from_secs(60);
+
+// token bucket for one client, refilled continuously up to the per minute limit
+pub struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    // takes a token, or returns how many seconds to wait for the next one
+    fn take(&mut self, per_minute: u32) -> Result<(), u64> {
+        let capacity = f64::from(per_minute);
+        let per_second = capacity / 60.0;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(capacity);
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - self.tokens) / per_second).ceil() as u64)
+        }
+    }
+}
+
+// identifies the client by the api key or basic auth username the auth check verified, or
+// else by its ip address. credentials that weren't checked are ignored, and so are
+// forwarded-for headers, since clients could choose them freely
+fn client_key(req: &ServiceRequest) -> String {
+    if let Some(ClientIdentity(identity)) = req.extensions().get::<ClientIdentity>() {
+        return identity.clone();
+    }
+    format!(
+        "ip:{}",
+        req.peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default()
+    )
+}
+
+// takes a token from the client's bucket, or returns how many seconds it has to wait
+async fn check_limit(data: &AppState, req: &ServiceRequest) -> Result<(), u64> {
+    let Some(per_minute) = data.config.lock().await.server.mutations_per_minute else {
+        return Ok(());
+    };
+    if per_minute == 0 {
+        return Ok(());
+    }
+
+    // an idle bucket has refilled completely after a minute, dropping it loses nothing and
+    // keeps the map to the clients of the last minute
+    let mut buckets = data.rate_limits.lock().await;
+    let now = Instant::now();
+    buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < REFILL_TIME);
+    buckets
+        .entry(client_key(req))
+        .or_insert(TokenBucket {
+            tokens: f64::from(per_minute),
+            updated_at: now,
+        })
+        .take(per_minute)
+}
+
+// middleware limiting how often a client may call the destructive endpoints, other
+// endpoints and servers without server.mutations_per_minute are not limited
+pub async fn limit_mutations(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let limited = req.match_pattern().is_some_and(|pattern| {
+        LIMITED_ROUTES
+            .iter()
+            .any(|(method, route)| req.method() == method && pattern == *route)
+    });
+
+    if let Some(data) = req.app_data::<web::Data<AppState>>().cloned() {
+        if limited {
+            if let Err(retry_after) = check_limit(&data, &req).await {
+                let response = HttpResponse::TooManyRequests()
+                    .insert_header(("Retry-After", retry_after.to_string()))
+                    .json(json!({ "error": "Rate limit exceeded, try again later" }));
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
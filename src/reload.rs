@@ -0,0 +1,39 @@
+use crate::checks::check_repositories;
+use crate::{load_config, AppState};
+use actix_web::web;
+use std::path::PathBuf;
+use tokio::signal::unix::{signal, SignalKind};
+
+// reloads config.toml every time the process receives SIGHUP, a config that fails to
+// load is logged and the running config is kept
+pub async fn reload_on_sighup(data: web::Data<AppState>, cli_path: Option<PathBuf>) {
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to listen for SIGHUP, config reloading is disabled");
+            return;
+        }
+    };
+
+    while sighup.recv().await.is_some() {
+        match load_config(cli_path.clone()) {
+            Ok(config) => {
+                let problems = check_repositories(&config);
+                if !problems.is_empty() {
+                    let problems = problems.join(", ");
+                    tracing::error!(problems, "invalid configuration, keeping the current one");
+                    continue;
+                }
+
+                *data.config.lock().await = config;
+                // cached stats may belong to a different repository now
+                data.stats_cache.lock().await.clear();
+                data.size_cache.lock().await.clear();
+                tracing::info!("reloaded configuration");
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to reload configuration, keeping the current one")
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::jobs::{accepted, spawn_job, JobQuery};
+use crate::lock::lock_writes;
+use crate::restic::{run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+// restic repair subcommands the endpoint may run, anything else is rejected while
+// deserializing the request
+#[derive(Deserialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum RepairTarget {
+    Index,
+    Snapshots,
+    Packs,
+}
+
+// request structure for the repair endpoint, packs needs the ids of the damaged packs
+#[derive(Deserialize, ToSchema)]
+struct RepairRequest {
+    what: RepairTarget,
+    #[serde(default)]
+    ids: Vec<String>,
+}
+
+impl RepairRequest {
+    // builds the restic repair arguments
+    fn args(&self) -> Vec<&str> {
+        let mut args = vec!["repair"];
+        match self.what {
+            RepairTarget::Index => args.push("index"),
+            RepairTarget::Snapshots => args.push("snapshots"),
+            RepairTarget::Packs => {
+                args.push("packs");
+                args.extend(self.ids.iter().map(String::as_str));
+            }
+        }
+        args
+    }
+}
+
+// function to repair the repository using restic repair
+async fn run_restic_repair(config: &Config, req: &RepairRequest) -> Result<String, ResticError> {
+    let output = run_restic(config, &req.args()).await?;
+
+    // restic reports what it repaired on stdout and problems on stderr, keep both
+    let mut report = String::from_utf8_lossy(&output.stdout).into_owned();
+    report.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(report)
+}
+
+// endpoint to repair a damaged repository (/repair)
+#[utoipa::path(
+    tag = "repository",
+    request_body = RepairRequest,
+    params(JobQuery),
+    responses(
+        (status = 200, description = "Restic output", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/repair")]
+async fn repair(
+    data: web::Data<AppState>,
+    req: web::Json<RepairRequest>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    if matches!(req.what, RepairTarget::Packs) {
+        if req.ids.is_empty() {
+            return Ok(HttpResponse::BadRequest()
+                .json(json!({ "error": "Repairing packs needs the pack ids" })));
+        }
+        if req
+            .ids
+            .iter()
+            .any(|id| id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Ok(HttpResponse::BadRequest().json(json!({ "error": "Invalid pack ID" })));
+        }
+    }
+
+    let guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+    let config = data.current_config().await;
+
+    if job.background {
+        let config = config.clone();
+        let req = req.into_inner();
+        let job_id = spawn_job(&data, "repair", async move {
+            let _guard = guard;
+            let output = run_restic_repair(&config, &req).await?;
+            Ok(json!({ "output": output }))
+        })
+        .await;
+        return Ok(accepted(job_id));
+    }
+
+    let output = run_restic_repair(&config, &req).await?;
+    Ok(HttpResponse::Ok().json(json!({ "output": output })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(repair);
+}
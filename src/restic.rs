@@ -0,0 +1,598 @@
+use crate::access_log::record_restic_time;
+use crate::locks::{parse_lock_error, UNLOCK_HINT};
+use crate::shutdown::{current_operation, OperationGuard};
+use crate::{Config, ErrorResponse};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{HttpResponse, ResponseError};
+use futures_util::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::future::Future;
+use std::process::{Output, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use utoipa::ToSchema;
+
+// error message returned when a restic command exceeds the configured timeout
+const TIMEOUT_ERROR: &str = "Restic command timed out";
+
+// error message returned when no restic process slot frees up in time
+const BUSY_ERROR: &str = "Too many restic commands are running, try again later";
+
+// error message returned when another process holds a lock on the repository
+const LOCKED_ERROR: &str = "Repository is locked by another process";
+
+// how long a restic command waits for a free slot before giving up
+const SLOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// error message returned when the restic binary can't be found
+pub const MISSING_BINARY_ERROR: &str = "restic binary not found; is it installed and on PATH?";
+
+// messages restic prints when a snapshot id doesn't match any snapshot
+const SNAPSHOT_NOT_FOUND: [&str; 2] = ["no matching ID found", "no snapshot matched"];
+
+// subcommands that don't modify the repository and are safe to retry, key is only
+// retried for key list
+const READ_ONLY_SUBCOMMANDS: [&str; 9] = [
+    "snapshots",
+    "list",
+    "stats",
+    "ls",
+    "cat",
+    "diff",
+    "find",
+    "dump",
+    "check",
+];
+
+// stderr fragments of network and backend errors that usually go away on their own
+const TRANSIENT_ERRORS: [&str; 10] = [
+    "connection reset",
+    "connection refused",
+    "i/o timeout",
+    "TLS handshake timeout",
+    "temporary failure in name resolution",
+    "unexpected EOF",
+    "broken pipe",
+    "500 Internal Server Error",
+    "502 Bad Gateway",
+    "503 Service Unavailable",
+];
+
+// delay before the first retry when server.retry_backoff_ms is not set
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 1000;
+
+// maximum number of stderr characters written to the log for a failed command
+const LOGGED_STDERR_CHARS: usize = 500;
+
+// what a failed restic process printed, returned with the error response
+#[derive(Debug)]
+pub struct ResticFailure {
+    pub exit_code: Option<i32>,
+    pub stderr: String,
+}
+
+// error from a restic helper, the variant decides the status and body of the response
+#[derive(Debug)]
+pub enum ResticError {
+    // the repository or the requested snapshot doesn't exist
+    NotFound(ResticFailure),
+    // another process holds a lock on the repository
+    Locked(ResticFailure),
+    // the repository password is wrong
+    WrongPassword(ResticFailure),
+    // a network or backend error that usually goes away on its own
+    BackendUnavailable(ResticFailure),
+    // restic failed for any other reason
+    Failed(ResticFailure),
+    // the command ran longer than server.command_timeout_secs
+    Timeout,
+    // no restic process slot freed up in time
+    Busy,
+    // the restic process couldn't be started or fed its input
+    SpawnFailed(String),
+    // anything else, like output that couldn't be parsed
+    Other(String),
+}
+
+impl ResticError {
+    // error for a restic process that exited unsuccessfully, classified by restic's
+    // documented exit codes and, for exit code 1, its stderr
+    pub fn failed(exit_code: Option<i32>, stderr: &str) -> Self {
+        let failure = ResticFailure {
+            exit_code,
+            stderr: stderr.to_string(),
+        };
+        match exit_code {
+            Some(10) => ResticError::NotFound(failure),
+            Some(11) => ResticError::Locked(failure),
+            Some(12) => ResticError::WrongPassword(failure),
+            _ if snapshot_not_found(stderr) => ResticError::NotFound(failure),
+            Some(1) if is_transient_error(stderr) => ResticError::BackendUnavailable(failure),
+            _ => ResticError::Failed(failure),
+        }
+    }
+
+    // the output of the restic process, None when restic never ran or didn't fail itself
+    pub fn failure(&self) -> Option<&ResticFailure> {
+        match self {
+            ResticError::NotFound(failure)
+            | ResticError::Locked(failure)
+            | ResticError::WrongPassword(failure)
+            | ResticError::BackendUnavailable(failure)
+            | ResticError::Failed(failure) => Some(failure),
+            _ => None,
+        }
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.failure().and_then(|failure| failure.exit_code)
+    }
+
+    pub fn stderr(&self) -> Option<&str> {
+        self.failure().map(|failure| failure.stderr.as_str())
+    }
+
+    // whether restic failed with a backend error that may go away when retried, the
+    // exit codes for a missing, locked or inaccessible repository are never transient
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ResticError::BackendUnavailable(_))
+    }
+
+    pub fn is_missing_binary(&self) -> bool {
+        matches!(self, ResticError::SpawnFailed(message) if message == MISSING_BINARY_ERROR)
+    }
+
+    // whether restic failed because the requested snapshot doesn't exist
+    pub fn is_snapshot_not_found(&self) -> bool {
+        matches!(self, ResticError::NotFound(failure) if snapshot_not_found(&failure.stderr))
+    }
+
+    // http status for the error
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ResticError::NotFound(_) => StatusCode::NOT_FOUND,
+            ResticError::Locked(_) => StatusCode::LOCKED,
+            ResticError::WrongPassword(_) => StatusCode::FORBIDDEN,
+            ResticError::BackendUnavailable(_) | ResticError::Busy => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ResticError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ResticError::SpawnFailed(_) if self.is_missing_binary() => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            // 1 is a failed command and 3 a backup that couldn't read all source data
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // the json body the error is answered with
+    pub fn body(&self) -> ErrorResponse {
+        let locked = matches!(self, ResticError::Locked(_));
+        ErrorResponse {
+            error: self.to_string(),
+            restic_exit_code: self.exit_code(),
+            stderr: self.stderr().map(str::to_string),
+            lock: self.stderr().filter(|_| locked).and_then(parse_lock_error),
+            hint: locked.then_some(UNLOCK_HINT),
+        }
+    }
+}
+
+impl From<String> for ResticError {
+    fn from(message: String) -> Self {
+        ResticError::Other(message)
+    }
+}
+
+impl From<&str> for ResticError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl fmt::Display for ResticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResticError::Timeout => f.write_str(TIMEOUT_ERROR),
+            ResticError::Busy => f.write_str(BUSY_ERROR),
+            ResticError::SpawnFailed(message) | ResticError::Other(message) => f.write_str(message),
+            ResticError::Locked(_) => f.write_str(LOCKED_ERROR),
+            _ => write!(f, "Restic error: {}", self.stderr().unwrap_or_default()),
+        }
+    }
+}
+
+// handlers return ResticError with ? and get the json error body from here
+impl ResponseError for ResticError {
+    fn status_code(&self) -> StatusCode {
+        self.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status()).json(self.body())
+    }
+}
+
+// checks restic's stderr for network and backend errors that usually go away on their own
+fn is_transient_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    TRANSIENT_ERRORS
+        .iter()
+        .any(|message| stderr.contains(&message.to_lowercase()))
+}
+
+tokio::task_local! {
+    // limits how many restic processes run at once, set around requests and background jobs
+    static RESTIC_SLOTS: Option<Arc<Semaphore>>;
+}
+
+// runs the future with restic commands limited to the permits of the semaphore, None
+// leaves them unlimited
+pub async fn with_restic_slots<F: Future>(slots: Option<Arc<Semaphore>>, future: F) -> F::Output {
+    RESTIC_SLOTS.scope(slots, future).await
+}
+
+// waits for a free restic process slot, commands outside a scope are not limited
+async fn acquire_slot(subcommand: &str) -> Result<Option<OwnedSemaphorePermit>, ResticError> {
+    let Some(slots) = RESTIC_SLOTS.try_with(Clone::clone).ok().flatten() else {
+        return Ok(None);
+    };
+
+    match tokio::time::timeout(SLOT_TIMEOUT, slots.acquire_owned()).await {
+        Ok(Ok(permit)) => Ok(Some(permit)),
+        _ => {
+            tracing::warn!(subcommand, "no free restic process slot");
+            Err(ResticError::Busy)
+        }
+    }
+}
+
+// error for a restic process that couldn't be started
+fn spawn_error(err: std::io::Error) -> ResticError {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        return ResticError::SpawnFailed(MISSING_BINARY_ERROR.to_string());
+    }
+    ResticError::SpawnFailed(format!("Failed to execute restic: {}", err))
+}
+
+// checks restic's stderr for a snapshot id that didn't match any snapshot
+pub fn snapshot_not_found(stderr: &str) -> bool {
+    SNAPSHOT_NOT_FOUND
+        .iter()
+        .any(|message| stderr.contains(message))
+}
+
+// optional upload and download limits in KiB/s, shared by the backup and restore requests
+#[derive(Deserialize, Default, ToSchema)]
+pub struct BandwidthLimits {
+    pub limit_upload: Option<u32>,
+    pub limit_download: Option<u32>,
+}
+
+impl BandwidthLimits {
+    // restic reads a limit of 0 as unlimited, so only positive limits are accepted
+    pub fn validate(&self) -> Result<(), String> {
+        if self.limit_upload == Some(0) || self.limit_download == Some(0) {
+            return Err("Bandwidth limits must be positive integers".to_string());
+        }
+        Ok(())
+    }
+
+    // builds the --limit-upload and --limit-download arguments
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(limit) = self.limit_upload {
+            args.extend(["--limit-upload".to_string(), limit.to_string()]);
+        }
+        if let Some(limit) = self.limit_download {
+            args.extend(["--limit-download".to_string(), limit.to_string()]);
+        }
+        args
+    }
+}
+
+// checks that a snapshot id is a full or shortened hex id or "latest", so it can't be read
+// as a flag or another argument by restic
+pub fn is_valid_snapshot_id(id: &str) -> bool {
+    id == "latest" || ((1..=64).contains(&id.len()) && id.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// rejects a snapshot id from a request before it is passed to restic
+pub fn validate_snapshot_id(id: &str) -> Result<(), HttpResponse> {
+    if is_valid_snapshot_id(id) {
+        Ok(())
+    } else {
+        Err(HttpResponse::BadRequest()
+            .json(json!({ "error": format!("Invalid snapshot ID \"{}\"", id) })))
+    }
+}
+
+// builds a restic command for the configured repository, the child is killed when
+// the command future or the child handle is dropped
+fn repo_command(config: &Config, args: &[&str]) -> Command {
+    let command_line = config.restic.command_line();
+    let mut command = Command::new(&command_line[0]);
+    command
+        .args(&command_line[1..])
+        .arg("-r")
+        .arg(config.repository.path())
+        .args(args)
+        .envs(&config.repository.env)
+        // other password sources inherited from the server would take precedence
+        .env_remove("RESTIC_PASSWORD_FILE")
+        .kill_on_drop(true);
+
+    // the password is handed over in the environment so it never touches the disk,
+    // a password command is left to restic so it runs for every invocation
+    match config.repository.password_command() {
+        Some(password_command) => command
+            .env("RESTIC_PASSWORD_COMMAND", password_command)
+            .env_remove("RESTIC_PASSWORD"),
+        None => command
+            .env("RESTIC_PASSWORD", config.repository.password())
+            .env_remove("RESTIC_PASSWORD_COMMAND"),
+    };
+    command
+}
+
+// runs a restic subcommand against the configured repository and returns its output
+// when the command succeeds
+pub async fn run_restic(config: &Config, args: &[&str]) -> Result<Output, ResticError> {
+    run_restic_with_input(config, args, None).await
+}
+
+// writes the input to the stdin of a restic process and collects its output
+async fn output_with_input(mut command: Command, input: &str) -> std::io::Result<Output> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes()).await?;
+    }
+    child.wait_with_output().await
+}
+
+// whether a restic command only reads from the repository
+fn is_read_only(args: &[&str]) -> bool {
+    match args {
+        ["key", "list", ..] => true,
+        [subcommand, ..] => READ_ONLY_SUBCOMMANDS.contains(subcommand),
+        [] => false,
+    }
+}
+
+// like run_restic, but feeds the input to restic's stdin, e.g. for passwords restic
+// would otherwise prompt for. read-only commands failing with a transient error are
+// retried up to server.max_retries times with exponential backoff, commands that modify
+// the repository are never retried since the failed attempt may have partially applied
+pub async fn run_restic_with_input(
+    config: &Config,
+    args: &[&str],
+    input: Option<&str>,
+) -> Result<Output, ResticError> {
+    let max_retries = if is_read_only(args) {
+        config.server.max_retries.unwrap_or(0)
+    } else {
+        0
+    };
+    let backoff_ms = config
+        .server
+        .retry_backoff_ms
+        .unwrap_or(DEFAULT_RETRY_BACKOFF_MS);
+
+    let mut attempt = 0;
+    loop {
+        match run_restic_once(config, args, input).await {
+            Err(err) if attempt < max_retries && err.is_transient() => {
+                attempt += 1;
+                let delay_ms = backoff_ms.saturating_mul(1 << (attempt - 1).min(16));
+                tracing::warn!(
+                    subcommand = args.first().copied().unwrap_or_default(),
+                    attempt,
+                    max_retries,
+                    delay_ms,
+                    "retrying restic command after a transient error"
+                );
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+// runs a single attempt of a restic command
+async fn run_restic_once(
+    config: &Config,
+    args: &[&str],
+    input: Option<&str>,
+) -> Result<Output, ResticError> {
+    // only the subcommand is logged, the remaining arguments may contain user paths
+    let subcommand = args.first().copied().unwrap_or_default();
+    let _slot = acquire_slot(subcommand).await?;
+
+    let mut command = repo_command(config, args);
+    tracing::info!(subcommand, "running restic");
+
+    let execute = async move {
+        match input {
+            Some(input) => output_with_input(command, input).await,
+            None => command.output().await,
+        }
+    };
+    wait_for_restic(config, subcommand, execute).await
+}
+
+// like run_restic, but streams the body to restic's stdin while it runs, e.g. for
+// restic backup --stdin. if reading the body fails restic is killed before it sees the
+// end of its input, so a partial upload never ends up in a snapshot
+pub async fn run_restic_with_stream<S, E>(
+    config: &Config,
+    args: &[&str],
+    mut body: S,
+) -> Result<Output, ResticError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: fmt::Display,
+{
+    let subcommand = args.first().copied().unwrap_or_default();
+    let _slot = acquire_slot(subcommand).await?;
+
+    let mut command = repo_command(config, args);
+    tracing::info!(subcommand, "running restic");
+
+    let execute = async move {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let mut stdin = child.stdin.take();
+        let write = async move {
+            if let Some(stdin) = stdin.as_mut() {
+                while let Some(chunk) = body.next().await {
+                    let chunk = chunk.map_err(|e| {
+                        std::io::Error::other(format!("failed to read the request body: {}", e))
+                    })?;
+                    stdin.write_all(&chunk).await?;
+                }
+            }
+            // closing stdin tells restic the data is complete
+            drop(stdin);
+            Ok(())
+        };
+
+        // an error writing the body drops the child, which kills restic
+        let ((), output) = tokio::try_join!(write, child.wait_with_output())?;
+        Ok(output)
+    };
+    wait_for_restic(config, subcommand, execute).await
+}
+
+// waits for a restic process started by execute and checks its exit status
+async fn wait_for_restic(
+    config: &Config,
+    subcommand: &str,
+    execute: impl Future<Output = std::io::Result<Output>>,
+) -> Result<Output, ResticError> {
+    // dropping the output future on timeout kills the restic process
+    let started = Instant::now();
+    let result = match config.server.command_timeout_secs {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), execute).await,
+        None => Ok(execute.await),
+    };
+    record_restic_time(started.elapsed());
+
+    let output = result
+        .map_err(|_| {
+            let secs = config.server.command_timeout_secs.unwrap_or_default();
+            tracing::error!(subcommand, timeout_secs = secs, "restic command timed out");
+            ResticError::Timeout
+        })?
+        .map_err(|e| {
+            tracing::error!(subcommand, error = %e, "failed to execute restic");
+            spawn_error(e)
+        })?;
+
+    // checks if the command executed successfully
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        tracing::error!(
+            subcommand,
+            status = %output.status,
+            stderr = %truncate(stderr.trim(), LOGGED_STDERR_CHARS),
+            "restic command failed"
+        );
+        return Err(ResticError::failed(output.status.code(), &stderr));
+    }
+
+    tracing::info!(subcommand, status = %output.status, "restic command finished");
+    Ok(output)
+}
+
+// shortens text to at most max characters for logging
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+// a restic process spawned for a streamed response, it holds its process slot and counts
+// as an active operation until it is dropped together with the response body
+pub struct ResticProcess {
+    pub child: Child,
+    _slot: Option<OwnedSemaphorePermit>,
+    _operation: Option<OperationGuard>,
+}
+
+// spawns a restic subcommand with piped stdout and stderr for callers that stream the output,
+// the process is killed if it is dropped before it finishes. no timeout is applied since
+// streams can legitimately run for as long as the client keeps reading
+pub async fn spawn_restic(config: &Config, args: &[&str]) -> Result<ResticProcess, ResticError> {
+    let subcommand = args.first().copied().unwrap_or_default();
+    let slot = acquire_slot(subcommand).await?;
+    tracing::info!(subcommand, "spawning restic");
+
+    let child = repo_command(config, args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    Ok(ResticProcess {
+        child,
+        _slot: slot,
+        _operation: current_operation(),
+    })
+}
+
+// runs restic version, which needs no repository, and returns the version number
+pub async fn restic_version(binary_path: &str) -> Result<String, ResticError> {
+    let output = Command::new(binary_path)
+        .arg("version")
+        .output()
+        .await
+        .map_err(spawn_error)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ResticError::failed(output.status.code(), &stderr));
+    }
+
+    // the output looks like "restic 0.16.4 compiled with go1.21.6 on linux/amd64"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .split_whitespace()
+        .nth(1)
+        .map(str::to_string)
+        .ok_or_else(|| format!("Unexpected restic version output: {}", stdout.trim()).into())
+}
+
+// parses the stdout of a restic command run with --json
+pub fn parse_json(output: Output) -> Result<Value, ResticError> {
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+    serde_json::from_str(&stdout).map_err(|e| format!("Failed to parse JSON: {}", e).into())
+}
+
+// parses the stdout of a restic command that prints one json object per line
+pub fn parse_json_lines(output: Output) -> Result<Vec<Value>, ResticError> {
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+    stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| format!("Failed to parse JSON: {}", e).into())
+        })
+        .collect()
+}
@@ -1,72 +1,407 @@
-use crate::AppState;
-use actix_web::{post, web, HttpResponse, Responder};
+use crate::jobs::{accepted, spawn_job, JobQuery};
+use crate::lock::lock_writes;
+use crate::restic::{run_restic, spawn_restic, validate_snapshot_id, BandwidthLimits, ResticError};
+use crate::sse::stream_restic_events;
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
 use serde::Deserialize;
 use serde_json::json;
-use std::io::Write;
-use std::process::Command;
-use tempfile::NamedTempFile;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
 
-// request structure for the restore endpoint
-#[derive(Deserialize)]
+// values restic restore accepts for --overwrite
+const OVERWRITE_MODES: [&str; 4] = ["always", "if-changed", "if-newer", "never"];
+
+// request structure for the restore endpoints, /snapshots/{id}/restore takes the
+// snapshot id from the path instead
+#[derive(Deserialize, ToSchema)]
 struct RestoreRequest {
+    #[serde(default)]
     snapshot_id: String,
+    // server.default_restore_dir/<snapshot id> when left out
+    #[serde(default)]
     target_dir: String,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    // how files that already exist in the target are treated, one of OVERWRITE_MODES
+    overwrite: Option<String>,
+    #[serde(flatten)]
+    limits: BandwidthLimits,
+}
+
+impl RestoreRequest {
+    // builds the restic restore arguments, the whole snapshot is restored
+    // when no include or exclude patterns are given
+    fn args(&self, target_dir: &str) -> Vec<String> {
+        let mut args = vec![
+            "restore".to_string(),
+            self.snapshot_id.clone(),
+            "--target".to_string(),
+            target_dir.to_string(),
+        ];
+
+        for pattern in &self.include {
+            args.push("--include".to_string());
+            args.push(pattern.clone());
+        }
+        for pattern in &self.exclude {
+            args.push("--exclude".to_string());
+            args.push(pattern.clone());
+        }
+        if let Some(overwrite) = &self.overwrite {
+            args.push("--overwrite".to_string());
+            args.push(overwrite.clone());
+        }
+        args.extend(self.limits.args());
+        args
+    }
+}
+
+// resolves symlinks and ".." in the target, which restic creates if it is missing,
+// by canonicalizing its deepest existing ancestor
+fn resolve_target(target: &Path) -> Result<PathBuf, String> {
+    let mut existing = target;
+    let mut missing = Vec::new();
+
+    while !existing.exists() {
+        // a ".." inside the missing part can't be resolved safely
+        let (Some(name), Some(parent)) = (existing.file_name(), existing.parent()) else {
+            return Err("Target directory can't be resolved".to_string());
+        };
+        missing.push(name);
+        existing = parent;
+    }
+
+    let mut resolved = existing
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve target directory: {}", e))?;
+    resolved.extend(missing.iter().rev());
+    Ok(resolved)
+}
+
+// checks that the target is an absolute path inside the configured restore root
+// and returns the resolved path to restore into
+fn validate_target(config: &Config, target: &Path) -> Result<PathBuf, String> {
+    if !target.is_absolute() {
+        return Err("Target directory must be an absolute path".to_string());
+    }
+
+    let resolved = resolve_target(target)?;
+
+    if let Some(root) = &config.server.restore_root {
+        let root = Path::new(root)
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve restore root: {}", e))?;
+        if !resolved.starts_with(&root) {
+            return Err(format!(
+                "Target directory must be inside the restore root {}",
+                root.display()
+            ));
+        }
+    }
+
+    Ok(resolved)
+}
+
+// validates a restore request and returns the resolved path to restore into
+fn validate_request(config: &Config, req: &RestoreRequest) -> Result<PathBuf, HttpResponse> {
+    if req.snapshot_id.is_empty() {
+        return Err(HttpResponse::BadRequest().json(json!({ "error": "Snapshot ID is required" })));
+    }
+    validate_snapshot_id(&req.snapshot_id)?;
+
+    let target_dir = if !req.target_dir.trim().is_empty() {
+        PathBuf::from(&req.target_dir)
+    } else if let Some(default_dir) = config.server.default_restore_dir.as_deref() {
+        Path::new(default_dir).join(&req.snapshot_id)
+    } else {
+        return Err(
+            HttpResponse::BadRequest().json(json!({ "error": "Target directory is required" }))
+        );
+    };
+
+    if let Err(err) = req.limits.validate() {
+        return Err(HttpResponse::BadRequest().json(json!({ "error": err })));
+    }
+
+    if let Some(overwrite) = req.overwrite.as_deref() {
+        if !OVERWRITE_MODES.contains(&overwrite) {
+            return Err(HttpResponse::BadRequest().json(json!({
+                "error": format!("Unknown overwrite mode, expected one of: {}", OVERWRITE_MODES.join(", "))
+            })));
+        }
+    }
+
+    validate_target(config, &target_dir)
+        .map_err(|err| HttpResponse::Forbidden().json(json!({ "error": err })))
 }
 
 // function to restore a snapshot using restic
 async fn restore_restic_snapshot(
-    repo_path: &str,
-    repo_password: &str,
-    snapshot_id: &str,
-    target_dir: &str,
-) -> Result<(), String> {
-    let mut password_file = NamedTempFile::new()
-        .map_err(|e| format!("Failed to create temp file for password: {}", e))?;
-    password_file
-        .write_all(repo_password.as_bytes())
-        .map_err(|e| format!("Failed to write password to temp file: {}", e))?;
-
-    let output = Command::new("restic")
-        .arg("-r")
-        .arg(repo_path)
-        .arg("--password-file")
-        .arg(password_file.path())
-        .arg("restore")
-        .arg(snapshot_id)
-        .arg("--target")
-        .arg(target_dir)
-        .output()
-        .map_err(|e| format!("Failed to execute restic: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Restic error: {}", stderr));
-    }
+    config: &Config,
+    req: &RestoreRequest,
+    target_dir: &Path,
+) -> Result<(), ResticError> {
+    let args = req.args(&target_dir.to_string_lossy());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    run_restic(config, &args).await?;
 
     Ok(())
 }
 
 // endpoint for restoring a snapshot
+#[utoipa::path(
+    tag = "snapshots",
+    request_body = RestoreRequest,
+    params(JobQuery),
+    responses(
+        (status = 200, description = "Snapshot restored", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 403, description = "Wrong repository password or target outside the restore root", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
 #[post("/restore")]
 async fn restore_snapshot(
     data: web::Data<AppState>,
     req: web::Json<RestoreRequest>,
-) -> impl Responder {
-    let config = data.config.lock().await;
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    restore(data, req.into_inner(), &job).await
+}
 
-    if req.target_dir.trim().is_empty() {
-        return HttpResponse::BadRequest().json(json!({ "error": "Target directory is required" }));
+// endpoint for restoring the snapshot in the path (/snapshots/{id}/restore), the body is
+// the same as for /restore without the snapshot id
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID"), JobQuery),
+    request_body = RestoreRequest,
+    responses(
+        (status = 200, description = "Snapshot restored", body = serde_json::Value),
+        (status = 202, description = "Started as a background job when ?async=true", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 403, description = "Wrong repository password or target outside the restore root", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/snapshots/{id}/restore")]
+async fn restore_snapshot_by_id(
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+    req: web::Json<RestoreRequest>,
+    job: web::Query<JobQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let mut req = req.into_inner();
+    if !req.snapshot_id.is_empty() && req.snapshot_id != *id {
+        return Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "snapshot_id in the body doesn't match the path" })));
     }
+    req.snapshot_id = id.into_inner();
+    restore(data, req, &job).await
+}
 
-    match restore_restic_snapshot(
-        &config.repository.path,
-        &config.repository.password,
-        &req.snapshot_id,
-        &req.target_dir,
+// response for a finished restore, with the directory in case the default was used
+fn restored(target_dir: &Path) -> serde_json::Value {
+    json!({
+        "message": "Snapshot restored successfully",
+        "target_dir": target_dir,
+    })
+}
+
+// shared handler logic for the restore endpoints
+async fn restore(
+    data: web::Data<AppState>,
+    req: RestoreRequest,
+    job: &JobQuery,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let target_dir = match validate_request(&config, &req) {
+        Ok(target_dir) => target_dir,
+        Err(response) => return Ok(response),
+    };
+
+    let guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+
+    if job.background {
+        let config = config.clone();
+        let job_id = spawn_job(&data, "restore", async move {
+            let _guard = guard;
+            restore_restic_snapshot(&config, &req, &target_dir).await?;
+            Ok(restored(&target_dir))
+        })
+        .await;
+        return Ok(accepted(job_id));
+    }
+
+    restore_restic_snapshot(&config, &req, &target_dir).await?;
+    Ok(HttpResponse::Ok().json(restored(&target_dir)))
+}
+
+// endpoint streaming restore progress as server-sent events, takes the same body as
+// /restore. the events are restic's status messages with percent_done and files_restored,
+// the last one is the restore summary
+#[utoipa::path(
+    tag = "snapshots",
+    request_body = RestoreRequest,
+    responses(
+        (status = 200, description = "Progress messages as server-sent events", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 403, description = "Target outside the restore root", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 429, description = "Rate limit exceeded", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
     )
-    .await
-    {
-        Ok(_) => HttpResponse::Ok().json(json!({ "message": "Snapshot restored successfully" })),
-        Err(err) => HttpResponse::InternalServerError().json(json!({ "error": err })),
+)]
+#[post("/restore/stream")]
+async fn restore_stream(
+    data: web::Data<AppState>,
+    req: web::Json<RestoreRequest>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let target_dir = match validate_request(&config, &req) {
+        Ok(target_dir) => target_dir,
+        Err(response) => return Ok(response),
+    };
+
+    let guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+
+    let mut args = req.args(&target_dir.to_string_lossy());
+    args.push("--json".to_string());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let child = spawn_restic(&config, &args).await?;
+    Ok(stream_restic_events(child, guard))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(include: &[&str], exclude: &[&str]) -> RestoreRequest {
+        RestoreRequest {
+            snapshot_id: "abc123".to_string(),
+            target_dir: "/restore".to_string(),
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            overwrite: None,
+            limits: BandwidthLimits::default(),
+        }
+    }
+
+    #[test]
+    fn restores_whole_snapshot_without_patterns() {
+        assert_eq!(
+            request(&[], &[]).args("/restore"),
+            ["restore", "abc123", "--target", "/restore"]
+        );
+    }
+
+    #[test]
+    fn adds_include_patterns() {
+        assert_eq!(
+            request(&["/home/user/docs", "/etc"], &[]).args("/restore"),
+            [
+                "restore",
+                "abc123",
+                "--target",
+                "/restore",
+                "--include",
+                "/home/user/docs",
+                "--include",
+                "/etc"
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_exclude_patterns() {
+        assert_eq!(
+            request(&[], &["*.tmp"]).args("/restore"),
+            [
+                "restore",
+                "abc123",
+                "--target",
+                "/restore",
+                "--exclude",
+                "*.tmp"
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_bandwidth_limits() {
+        let mut req = request(&[], &[]);
+        req.limits.limit_download = Some(1024);
+        assert_eq!(
+            req.args("/restore"),
+            [
+                "restore",
+                "abc123",
+                "--target",
+                "/restore",
+                "--limit-download",
+                "1024"
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_overwrite_mode() {
+        let mut req = request(&[], &[]);
+        req.overwrite = Some("if-changed".to_string());
+        assert_eq!(
+            req.args("/restore"),
+            [
+                "restore",
+                "abc123",
+                "--target",
+                "/restore",
+                "--overwrite",
+                "if-changed"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_overwrite_modes() {
+        let mut req = request(&[], &[]);
+        req.overwrite = Some("sometimes".to_string());
+        let config: Config = toml::from_str(
+            "[repository]\npath = \"repo\"\n[server]\nip = \"127.0.0.1\"\nport = 8080\n",
+        )
+        .unwrap();
+        let response = validate_request(&config, &req).unwrap_err();
+        assert_eq!(response.status(), 400);
+    }
+
+    #[test]
+    fn adds_include_and_exclude_patterns() {
+        assert_eq!(
+            request(&["/home"], &["*.tmp"]).args("/restore"),
+            [
+                "restore",
+                "abc123",
+                "--target",
+                "/restore",
+                "--include",
+                "/home",
+                "--exclude",
+                "*.tmp"
+            ]
+        );
     }
 }
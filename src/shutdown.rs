@@ -0,0 +1,108 @@
+use crate::restic::with_restic_slots;
+use crate::AppState;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServerHandle, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// default time to wait for in-flight operations when no grace period is configured
+pub const DEFAULT_GRACE_SECS: u64 = 30;
+
+// counts an operation as active until the guard is dropped, even if the operation fails
+pub struct OperationGuard(Arc<AtomicUsize>);
+
+impl OperationGuard {
+    pub fn new(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        OperationGuard(counter.clone())
+    }
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+tokio::task_local! {
+    // the in-flight counter of the request the task serves, for streamed responses that
+    // keep restic running after the handler returned
+    static ACTIVE_OPERATIONS: Arc<AtomicUsize>;
+}
+
+// counts one more active operation for the current request until the guard is dropped,
+// None outside of a request
+pub fn current_operation() -> Option<OperationGuard> {
+    ACTIVE_OPERATIONS.try_with(OperationGuard::new).ok()
+}
+
+// middleware counting in-flight requests, every restic command runs inside one so
+// shutdown can wait for them to finish, it also applies server.max_concurrent_restic
+pub async fn track_operation(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(data) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    let _guard = OperationGuard::new(&data.active_operations);
+    let counter = data.active_operations.clone();
+    ACTIVE_OPERATIONS
+        .scope(
+            counter,
+            with_restic_slots(data.restic_slots.clone(), next.call(req)),
+        )
+        .await
+}
+
+// resolves once the process receives SIGINT or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to listen for SIGTERM");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// waits for a shutdown signal, stops accepting connections and gives in-flight restic
+// operations up to the grace period to finish before the server is stopped
+pub async fn graceful_shutdown(handle: ServerHandle, data: web::Data<AppState>, grace: Duration) {
+    shutdown_signal().await;
+    handle.pause().await;
+
+    let deadline = Instant::now() + grace;
+    loop {
+        let active = data.active_operations.load(Ordering::SeqCst);
+        if active == 0 {
+            tracing::info!("no active operations, shutting down");
+            break;
+        }
+        if Instant::now() >= deadline {
+            tracing::warn!(active, "grace period expired, stopping active operations");
+            break;
+        }
+        tracing::info!(active, "waiting for active operations before shutting down");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    handle.stop(true).await;
+}
@@ -0,0 +1,62 @@
+use crate::restic::{parse_json, run_restic, ResticError};
+use crate::snapshot::Snapshot;
+use crate::Config;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+// how many restic stats processes run at once while sizes are looked up
+const SIZE_LOOKUPS: usize = 4;
+
+// restore size of a single snapshot
+async fn snapshot_size(config: &Config, snapshot_id: &str) -> Result<u64, ResticError> {
+    let output = run_restic(config, &["stats", "--json", snapshot_id]).await?;
+    Ok(parse_json(output)?["total_size"]
+        .as_u64()
+        .unwrap_or_default())
+}
+
+// adds the restore sizes of the snapshots to the cache, snapshots never change so only
+// ids missing from it are passed to restic stats, up to SIZE_LOOKUPS at a time
+pub async fn lookup_sizes(
+    config: &Config,
+    cache: &mut HashMap<String, u64>,
+    snapshot_ids: &[&str],
+) -> Result<(), ResticError> {
+    let mut missing: Vec<&str> = snapshot_ids
+        .iter()
+        .copied()
+        .filter(|id| !cache.contains_key(*id))
+        .collect();
+    missing.sort_unstable();
+    missing.dedup();
+
+    let sizes: Vec<(String, u64)> = stream::iter(missing)
+        .map(|id| async move {
+            Ok::<_, ResticError>((id.to_string(), snapshot_size(config, id).await?))
+        })
+        .buffer_unordered(SIZE_LOOKUPS)
+        .try_collect()
+        .await?;
+    cache.extend(sizes);
+    Ok(())
+}
+
+// fills in size_bytes of the snapshots from the size cache, looking up missing sizes
+pub async fn attach_sizes(
+    config: &Config,
+    cache: &Mutex<HashMap<String, u64>>,
+    snapshots: &mut [Snapshot],
+) -> Result<(), ResticError> {
+    let mut cache = cache.lock().await;
+    let ids: Vec<&str> = snapshots
+        .iter()
+        .map(|snapshot| snapshot.id.as_str())
+        .collect();
+    lookup_sizes(config, &mut cache, &ids).await?;
+
+    for snapshot in snapshots {
+        snapshot.size_bytes = cache.get(&snapshot.id).copied();
+    }
+    Ok(())
+}
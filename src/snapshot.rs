@@ -0,0 +1,179 @@
+use crate::restic::ResticError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::process::Output;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+// a snapshot as returned by the api, deserialized from restic's json so clients don't
+// depend on restic's exact output and fields restic leaves out are always present
+#[derive(Deserialize, Serialize, Clone, ToSchema)]
+pub struct Snapshot {
+    // full 64 character snapshot id
+    pub id: String,
+    // first 8 characters of the id, as shown by restic
+    #[serde(default)]
+    pub short_id: String,
+    // rfc3339 timestamp of when the backup was taken
+    pub time: String,
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default)]
+    pub username: String,
+    // backed up paths
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // restore size from restic stats, only filled in and serialized for ?with_size=true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+}
+
+// window snapshots must have been taken in, both ends are inclusive and optional
+#[derive(Default, Clone, Copy)]
+pub struct TimeRange {
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+}
+
+impl TimeRange {
+    // parses the since and until rfc3339 timestamps, empty values are ignored
+    pub fn parse(since: Option<&str>, until: Option<&str>) -> Result<Self, String> {
+        let parse = |name: &str, value: Option<&str>| {
+            value
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    OffsetDateTime::parse(value, &Rfc3339).map_err(|_| {
+                        format!(
+                            "{} must be an RFC3339 timestamp like 2024-01-01T00:00:00Z",
+                            name
+                        )
+                    })
+                })
+                .transpose()
+        };
+
+        let range = TimeRange {
+            since: parse("since", since)?,
+            until: parse("until", until)?,
+        };
+        if let (Some(since), Some(until)) = (range.since, range.until) {
+            if since > until {
+                return Err("since must not be after until".to_string());
+            }
+        }
+        Ok(range)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    // whether the snapshot was taken in the window, snapshots with an unreadable time
+    // only match when no window is set
+    pub fn contains(&self, snapshot: &Snapshot) -> bool {
+        if !self.is_set() {
+            return true;
+        }
+        OffsetDateTime::parse(&snapshot.time, &Rfc3339).is_ok_and(|time| {
+            self.since.is_none_or(|since| time >= since)
+                && self.until.is_none_or(|until| time <= until)
+        })
+    }
+}
+
+// fields restic snapshots --group-by accepts, comma separated
+pub const GROUP_BY_FIELDS: [&str; 3] = ["host", "paths", "tags"];
+
+// a group of snapshots as printed by restic snapshots --group-by
+#[derive(Deserialize)]
+struct SnapshotGroup {
+    group_key: GroupKey,
+    snapshots: Option<Vec<Snapshot>>,
+}
+
+// the values a group shares, restic leaves the fields it didn't group by empty
+#[derive(Deserialize)]
+struct GroupKey {
+    #[serde(default)]
+    hostname: String,
+    paths: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+}
+
+impl GroupKey {
+    // label for the group made of the grouped fields in the requested order, lists are
+    // comma separated and fields are separated by "|"
+    fn label(&self, group_by: &[&str]) -> String {
+        group_by
+            .iter()
+            .map(|field| match *field {
+                "host" => self.hostname.clone(),
+                "paths" => self.paths.as_deref().unwrap_or_default().join(","),
+                _ => self.tags.as_deref().unwrap_or_default().join(","),
+            })
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+}
+
+// fills in the short id for restic versions that don't print it
+fn with_short_id(mut snapshot: Snapshot) -> Snapshot {
+    if snapshot.short_id.is_empty() {
+        snapshot.short_id = snapshot.id.chars().take(8).collect();
+    }
+    snapshot
+}
+
+// parses the output of restic snapshots --json
+pub fn parse_snapshots(output: Output) -> Result<Vec<Snapshot>, ResticError> {
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+
+    // restic prints null, or with some versions nothing, instead of an empty list for an
+    // empty repository
+    if stdout.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let snapshots: Option<Vec<Snapshot>> =
+        serde_json::from_str(&stdout).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+
+    Ok(snapshots
+        .unwrap_or_default()
+        .into_iter()
+        .map(with_short_id)
+        .collect())
+}
+
+// parses the output of restic snapshots --json --group-by into a map from group label
+// to the snapshots in that group
+pub fn parse_snapshot_groups(
+    output: Output,
+    group_by: &[&str],
+) -> Result<BTreeMap<String, Vec<Snapshot>>, ResticError> {
+    let stdout =
+        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+
+    if stdout.trim().is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let groups: Option<Vec<SnapshotGroup>> =
+        serde_json::from_str(&stdout).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+
+    let mut grouped: BTreeMap<String, Vec<Snapshot>> = BTreeMap::new();
+    for group in groups.unwrap_or_default() {
+        grouped
+            .entry(group.group_key.label(group_by))
+            .or_default()
+            .extend(
+                group
+                    .snapshots
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(with_short_id),
+            );
+    }
+    Ok(grouped)
+}
@@ -0,0 +1,96 @@
+use crate::lock::WriteGuard;
+use crate::restic::{ResticError, ResticProcess};
+use actix_web::http::header::ContentEncoding;
+use actix_web::web::Bytes;
+use actix_web::{HttpResponse, ResponseError};
+use futures_util::stream;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader, Lines};
+use tokio::process::ChildStdout;
+use tokio::task::JoinHandle;
+
+// state carried between the events of a restic progress stream
+struct EventStream {
+    lines: Lines<BufReader<ChildStdout>>,
+    process: ResticProcess,
+    stderr: Option<JoinHandle<String>>,
+    finished: bool,
+    _guard: WriteGuard,
+}
+
+// formats a single server-sent event
+fn sse_event(event: Option<&str>, data: &str) -> Bytes {
+    match event {
+        Some(event) => Bytes::from(format!("event: {}\ndata: {}\n\n", event, data)),
+        None => Bytes::from(format!("data: {}\n\n", data)),
+    }
+}
+
+// reads the next event, forwarding each json line restic prints and finishing with an
+// error event when restic exits unsuccessfully
+async fn next_event(
+    mut state: EventStream,
+) -> Option<(Result<Bytes, actix_web::Error>, EventStream)> {
+    if state.finished {
+        return None;
+    }
+
+    loop {
+        match state.lines.next_line().await {
+            Ok(Some(line)) if line.trim().is_empty() => continue,
+            Ok(Some(line)) => return Some((Ok(sse_event(None, &line)), state)),
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    state.finished = true;
+    let exit_code = match state.process.child.wait().await {
+        Ok(status) if status.success() => return None,
+        Ok(status) => status.code(),
+        Err(_) => None,
+    };
+
+    let stderr = match state.stderr.take() {
+        Some(handle) => handle.await.unwrap_or_default(),
+        None => String::new(),
+    };
+    let err = ResticError::failed(exit_code, &stderr);
+    let data: Value = json!({
+        "error": err.to_string(),
+        "restic_exit_code": err.exit_code(),
+        "stderr": err.stderr(),
+    });
+    Some((Ok(sse_event(Some("error"), &data.to_string())), state))
+}
+
+// streams the json progress messages of a restic process run with --json as
+// server-sent events, the stream owns the process so it is killed if the client disconnects,
+// and the write lock so it is held until restic is done
+pub fn stream_restic_events(mut process: ResticProcess, guard: WriteGuard) -> HttpResponse {
+    let child = &mut process.child;
+    let (Some(stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return ResticError::from("Failed to capture restic output").error_response();
+    };
+
+    // stderr is drained in the background so a chatty restic can't block on a full pipe
+    let stderr = actix_web::rt::spawn(async move {
+        let mut message = String::new();
+        let _ = stderr.read_to_string(&mut message).await;
+        message
+    });
+
+    let state = EventStream {
+        lines: BufReader::new(stdout).lines(),
+        process,
+        stderr: Some(stderr),
+        finished: false,
+        _guard: guard,
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // compressing would hold events back until the encoder's buffer fills up
+        .insert_header(ContentEncoding::Identity)
+        .streaming(stream::unfold(state, next_event))
+}
@@ -1,57 +1,260 @@
-use crate::AppState;
-use actix_web::{get, web, HttpResponse, Responder};
+use crate::etag::json_with_etag;
+use crate::output::{text_response, OutputFormat};
+use crate::restic::{parse_json, run_restic, validate_snapshot_id, ResticError};
+use crate::{AppState, Config};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
 use serde_json::{json, Value};
-use std::io::Write;
-use std::process::Command;
-use tempfile::NamedTempFile;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use utoipa::IntoParams;
+
+// modes restic stats accepts for --mode
+const STATS_MODES: [&str; 4] = [
+    "restore-size",
+    "files-by-contents",
+    "raw-data",
+    "blobs-count",
+];
+
+// errors restic stats fails with for a repository without snapshots, depending on version
+const NO_SNAPSHOTS: [&str; 2] = ["no snapshots found", "no snapshot found"];
+
+// a stats result together with when it was fetched
+pub struct CachedStats {
+    value: Value,
+    fetched_at: Instant,
+}
+
+// cached stats keyed by mode and no_lock, a reading without a lock can't answer a request
+// that would have failed on one. a cell is filled by the first request that needs it, so
+// concurrent requests for the same key share one restic call
+pub type StatsCache = HashMap<(Option<String>, bool), Arc<OnceCell<CachedStats>>>;
+
+// query parameters for the stats endpoints
+#[derive(Deserialize, IntoParams)]
+struct StatsQuery {
+    #[serde(default)]
+    refresh: bool,
+    // one of STATS_MODES, restic defaults to restore-size
+    mode: Option<String>,
+    // "text" for restic's human readable output
+    format: Option<String>,
+    // read without locking the repository, e.g. while a backup is running
+    #[serde(default)]
+    no_lock: bool,
+}
+
+impl StatsQuery {
+    // the requested mode, or a 400 response for a mode restic doesn't know
+    fn mode(&self) -> Result<Option<&str>, HttpResponse> {
+        match self.mode.as_deref().filter(|mode| !mode.is_empty()) {
+            Some(mode) if !STATS_MODES.contains(&mode) => {
+                Err(HttpResponse::BadRequest().json(json!({
+                    "error": format!("Unknown stats mode, expected one of: {}", STATS_MODES.join(", "))
+                })))
+            }
+            mode => Ok(mode),
+        }
+    }
+
+    // the requested output format, or a 400 response for an unknown one
+    fn format(&self, req: &HttpRequest) -> Result<OutputFormat, HttpResponse> {
+        OutputFormat::from_request(req, self.format.as_deref())
+            .map_err(|e| HttpResponse::BadRequest().json(json!({ "error": e })))
+    }
+}
+
+// builds the restic stats arguments for the whole repository or a single snapshot
+fn stats_args<'a>(
+    mode: Option<&'a str>,
+    snapshot_id: Option<&'a str>,
+    no_lock: bool,
+) -> Vec<&'a str> {
+    let mut args = vec!["stats", "--json"];
+    if let Some(mode) = mode {
+        args.extend(["--mode", mode]);
+    }
+    if no_lock {
+        args.push("--no-lock");
+    }
+    args.extend(snapshot_id);
+    args
+}
 
 // function to retrieve stats from restic repository using the restic cli
-async fn get_restic_stats(repo_path: &str, repo_password: &str) -> Result<Value, String> {
-    // creates a temporary file to store the repository password securely
-    let mut password_file = NamedTempFile::new()
-        .map_err(|e| format!("Failed to create temp file for password: {}", e))?;
-
-    // write the password to the temporary file
-    password_file
-        .write_all(repo_password.as_bytes())
-        .map_err(|e| format!("Failed to write password to temp file: {}", e))?;
-
-    // executes the restic cli command to fetch stats in json format
-    let output = Command::new("restic")
-        .arg("-r")
-        .arg(repo_path)
-        .arg("--password-file")
-        .arg(password_file.path())
-        .arg("stats")
-        .arg("--json")
-        .output()
-        .map_err(|e| format!("Failed to execute restic: {}", e))?;
-
-    // checks if the command executed successfully
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Restic error: {}", stderr));
-    }
-
-    // parses the json output from the restic command
-    let stdout =
-        String::from_utf8(output.stdout).map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
-    let json: Value =
-        serde_json::from_str(&stdout).map_err(|e| format!("Failed to parse JSON: {}", e))?;
-    Ok(json)
-}
-
-// endpoint to retrieve restic stats (/stats)
+pub async fn get_restic_stats(config: &Config, mode: Option<&str>) -> Result<Value, ResticError> {
+    get_restic_snapshot_stats(config, mode, None, false).await
+}
+
+// function to retrieve stats for the whole repository, or only for a single snapshot
+pub async fn get_restic_snapshot_stats(
+    config: &Config,
+    mode: Option<&str>,
+    snapshot_id: Option<&str>,
+    no_lock: bool,
+) -> Result<Value, ResticError> {
+    let output = match run_restic(config, &stats_args(mode, snapshot_id, no_lock)).await {
+        Ok(output) => output,
+        Err(err) if snapshot_id.is_none() && is_empty_repository(&err) => return Ok(empty_stats()),
+        Err(err) => return Err(err),
+    };
+
+    // some versions print nothing or null instead of zeroed stats for an empty repository
+    match output.stdout.trim_ascii() {
+        b"" | b"null" if snapshot_id.is_none() => Ok(empty_stats()),
+        _ => parse_json(output),
+    }
+}
+
+// whether restic stats failed because the repository has no snapshots yet
+fn is_empty_repository(err: &ResticError) -> bool {
+    let stderr = err.stderr().unwrap_or_default().to_lowercase();
+    NO_SNAPSHOTS.iter().any(|message| stderr.contains(message))
+}
+
+// the stats of a freshly initialized repository
+fn empty_stats() -> Value {
+    json!({ "total_size": 0, "total_file_count": 0, "snapshots_count": 0 })
+}
+
+// function to retrieve restic's human readable stats, which are never cached
+async fn get_restic_stats_text(
+    config: &Config,
+    mode: Option<&str>,
+    snapshot_id: Option<&str>,
+    no_lock: bool,
+) -> Result<HttpResponse, ResticError> {
+    let args = OutputFormat::Text.apply(stats_args(mode, snapshot_id, no_lock));
+    Ok(text_response(run_restic(config, &args).await?))
+}
+
+// endpoint to retrieve restic stats (/stats?mode=raw-data), results are cached for
+// server.stats_cache_secs and ?refresh=true bypasses the cache
+#[utoipa::path(
+    tag = "repository",
+    params(StatsQuery),
+    responses(
+        (status = 200, description = "Repository stats from restic stats --json, or restic's human readable output with format=text", content(
+            (serde_json::Value = "application/json"),
+            (String = "text/plain"),
+        )),
+        (status = 400, description = "Unknown stats mode or format", body = crate::ErrorResponse),
+        (status = 403, description = "Wrong repository password", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 423, description = "Repository is locked", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
 #[get("/stats")]
-async fn stats(data: web::Data<AppState>) -> impl Responder {
-    let config = data.config.lock().await;
+async fn stats(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+) -> Result<HttpResponse, ResticError> {
+    let mode = match query.mode() {
+        Ok(mode) => mode,
+        Err(response) => return Ok(response),
+    };
+    let format = match query.format(&req) {
+        Ok(format) => format,
+        Err(response) => return Ok(response),
+    };
+
+    let config = data.current_config().await;
+    if format == OutputFormat::Text {
+        return get_restic_stats_text(&config, mode, None, query.no_lock).await;
+    }
+    let ttl = Duration::from_secs(config.server.stats_cache_secs.unwrap_or_default());
+    if ttl.is_zero() {
+        let json = get_restic_snapshot_stats(&config, mode, None, query.no_lock).await?;
+        return Ok(json_with_etag(&req, &json));
+    }
+
+    // the cache lock is only held to pick the entry, so requests for another key or with
+    // ?refresh=true don't wait for each other's restic calls. an entry that is still being
+    // fetched is shared
+    let key = (mode.map(str::to_string), query.no_lock);
+    let cell = {
+        let mut cache = data.stats_cache.lock().await;
+        let fresh = cache.get(&key).filter(|cell| {
+            !query.refresh
+                && cell
+                    .get()
+                    .is_none_or(|cached| cached.fetched_at.elapsed() < ttl)
+        });
+        match fresh.cloned() {
+            Some(cell) => cell,
+            None => {
+                let cell = Arc::new(OnceCell::new());
+                cache.insert(key, cell.clone());
+                cell
+            }
+        }
+    };
+
+    let cached = cell
+        .get_or_try_init(|| async {
+            let value = get_restic_snapshot_stats(&config, mode, None, query.no_lock).await?;
+            Ok::<_, ResticError>(CachedStats {
+                value,
+                fetched_at: Instant::now(),
+            })
+        })
+        .await?;
+    Ok(json_with_etag(&req, &cached.value))
+}
+
+// endpoint to retrieve the size and file count of a single snapshot
+// (/snapshots/{id}/stats?mode=raw-data), these are never cached
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID"), StatsQuery),
+    responses(
+        (status = 200, description = "Snapshot stats from restic stats --json", body = serde_json::Value),
+        (status = 400, description = "Invalid snapshot ID or unknown stats mode", body = crate::ErrorResponse),
+        (status = 404, description = "Not found", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[get("/snapshots/{id}/stats")]
+async fn snapshot_stats(
+    req: HttpRequest,
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(&id) {
+        return Ok(response);
+    }
+    let mode = match query.mode() {
+        Ok(mode) => mode,
+        Err(response) => return Ok(response),
+    };
+    let format = match query.format(&req) {
+        Ok(format) => format,
+        Err(response) => return Ok(response),
+    };
+
+    let config = data.current_config().await;
 
-    match get_restic_stats(&config.repository.path, &config.repository.password).await {
-        Ok(json) => HttpResponse::Ok().json(json),
-        Err(err) => HttpResponse::InternalServerError().json(json!({ "error": err })),
+    let result = match format {
+        OutputFormat::Text => get_restic_stats_text(&config, mode, Some(&id), query.no_lock).await,
+        OutputFormat::Json => get_restic_snapshot_stats(&config, mode, Some(&id), query.no_lock)
+            .await
+            .map(|json| HttpResponse::Ok().json(json)),
+    };
+    match result {
+        Ok(response) => Ok(response),
+        Err(err) if err.is_snapshot_not_found() => {
+            Ok(HttpResponse::NotFound().json(json!({ "error": "snapshot not found" })))
+        }
+        Err(err) => Err(err),
     }
 }
 
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(stats);
+    cfg.service(stats).service(snapshot_stats);
 }
@@ -0,0 +1,117 @@
+use crate::lock::lock_writes;
+use crate::restic::{run_restic, validate_snapshot_id, ResticError};
+use crate::{AppState, Config};
+use actix_web::{delete, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+// message restic prints when a tag change did not touch any snapshot
+const NOTHING_MODIFIED: &str = "no snapshots were modified";
+
+// request structure for the tag endpoints
+#[derive(Deserialize, ToSchema)]
+struct TagsRequest {
+    tags: Vec<String>,
+}
+
+// function to add or remove tags on a snapshot, returns false when nothing changed
+async fn change_restic_tags(
+    config: &Config,
+    snapshot_id: &str,
+    flag: &str,
+    tags: &[String],
+) -> Result<bool, ResticError> {
+    let mut args = vec!["tag"];
+    for tag in tags {
+        args.extend([flag, tag.trim()]);
+    }
+    args.push(snapshot_id);
+
+    // depending on the version restic reports an unchanged snapshot on stdout or as an error
+    match run_restic(config, &args).await {
+        Ok(output) => Ok(!String::from_utf8_lossy(&output.stdout).contains(NOTHING_MODIFIED)),
+        Err(err) if err.to_string().contains(NOTHING_MODIFIED) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+// shared handler logic for adding and removing tags
+async fn update_tags(
+    data: web::Data<AppState>,
+    snapshot_id: &str,
+    flag: &str,
+    req: &TagsRequest,
+) -> Result<HttpResponse, ResticError> {
+    if let Err(response) = validate_snapshot_id(snapshot_id) {
+        return Ok(response);
+    }
+    if req.tags.is_empty() {
+        return Ok(
+            HttpResponse::BadRequest().json(json!({ "error": "At least one tag is required" }))
+        );
+    }
+    // restic would write a blank tag into the snapshot
+    if req.tags.iter().any(|tag| tag.trim().is_empty()) {
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": "Tags must not be blank" })));
+    }
+
+    let _guard = match lock_writes(&data) {
+        Ok(guard) => guard,
+        Err(response) => return Ok(response),
+    };
+    let config = data.current_config().await;
+
+    let message = if change_restic_tags(&config, snapshot_id, flag, &req.tags).await? {
+        "Snapshot tags updated successfully"
+    } else {
+        "Nothing changed, no snapshots were modified"
+    };
+    Ok(HttpResponse::Ok().json(json!({ "message": message })))
+}
+
+// endpoint to add tags to a snapshot (/snapshots/{id}/tags)
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID")),
+    request_body = TagsRequest,
+    responses(
+        (status = 200, description = "Tags updated, or nothing changed", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/snapshots/{id}/tags")]
+async fn add_tags(
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+    req: web::Json<TagsRequest>,
+) -> impl Responder {
+    update_tags(data, &id, "--add", &req).await
+}
+
+// endpoint to remove tags from a snapshot (/snapshots/{id}/tags)
+#[utoipa::path(
+    tag = "snapshots",
+    params(("id" = String, Path, description = "Snapshot ID")),
+    request_body = TagsRequest,
+    responses(
+        (status = 200, description = "Tags updated, or nothing changed", body = serde_json::Value),
+        (status = 400, description = "Invalid request", body = crate::ErrorResponse),
+        (status = 409, description = "Another write operation is in progress", body = crate::ErrorResponse),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[delete("/snapshots/{id}/tags")]
+async fn remove_tags(
+    id: web::Path<String>,
+    data: web::Data<AppState>,
+    req: web::Json<TagsRequest>,
+) -> impl Responder {
+    update_tags(data, &id, "--remove", &req).await
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(add_tags).service(remove_tags);
+}
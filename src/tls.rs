@@ -0,0 +1,28 @@
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fs::File;
+use std::io::BufReader;
+
+// loads the pem encoded certificate chain and private key into a rustls server config
+pub fn load_rustls_config(cert_path: &str, key_path: &str) -> Result<rustls::ServerConfig, String> {
+    let cert_file = File::open(cert_path)
+        .map_err(|e| format!("Failed to open TLS certificate {}: {}", cert_path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<CertificateDer>, _>>()
+        .map_err(|e| format!("Failed to parse TLS certificate {}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", cert_path));
+    }
+
+    let key_file =
+        File::open(key_path).map_err(|e| format!("Failed to open TLS key {}: {}", key_path, e))?;
+    let key: PrivateKeyDer = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+        .map_err(|e| format!("Failed to parse TLS key {}: {}", key_path, e))?
+        .ok_or_else(|| format!("No private key found in {}", key_path))?;
+
+    rustls::ServerConfig::builder_with_provider(rustls::crypto::ring::default_provider().into())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Failed to configure TLS: {}", e))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid TLS certificate or key: {}", e))
+}
@@ -0,0 +1,55 @@
+use crate::optional_json::OptionalJson;
+use crate::restic::{run_restic, ResticError};
+use crate::{AppState, Config};
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+// request structure for the unlock endpoint, the body is optional
+#[derive(Deserialize, Default, ToSchema)]
+struct UnlockRequest {
+    #[serde(default)]
+    remove_all: bool,
+}
+
+// function to remove stale locks using restic unlock
+async fn unlock_restic_repository(
+    config: &Config,
+    remove_all: bool,
+) -> Result<String, ResticError> {
+    let mut args = vec!["unlock"];
+    if remove_all {
+        args.push("--remove-all");
+    }
+
+    let output = run_restic(config, &args).await?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// endpoint to clear stale repository locks (/unlock)
+#[utoipa::path(
+    tag = "repository",
+    request_body(content = Option<UnlockRequest>),
+    responses(
+        (status = 200, description = "Locks removed", body = serde_json::Value),
+        (status = 500, description = "Restic failed", body = crate::ErrorResponse),
+    )
+)]
+#[post("/unlock")]
+async fn unlock(
+    data: web::Data<AppState>,
+    OptionalJson(req): OptionalJson<UnlockRequest>,
+) -> Result<HttpResponse, ResticError> {
+    let config = data.current_config().await;
+
+    let output = unlock_restic_repository(&config, req.remove_all).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "message": "Repository unlocked successfully",
+        "output": output,
+    })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(unlock);
+}
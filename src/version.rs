@@ -0,0 +1,23 @@
+use crate::AppState;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde_json::json;
+
+// endpoint reporting the api and restic versions (/version), the restic version
+// is detected once at startup and is null if restic was not available then
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "API and restic versions", body = serde_json::Value),
+    )
+)]
+#[get("/version")]
+async fn version(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "api_version": env!("CARGO_PKG_VERSION"),
+        "restic_version": data.restic_version,
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(version);
+}
@@ -0,0 +1,123 @@
+use crate::AppState;
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+// write operations that are reported to server.webhook_url, with their operation name
+const NOTIFIED_ROUTES: [(Method, &str, &str); 12] = [
+    (Method::POST, "/backup", "backup"),
+    (Method::POST, "/backup/stdin", "backup"),
+    (Method::POST, "/backup/{profile}", "backup"),
+    (Method::POST, "/restore", "restore"),
+    (Method::POST, "/snapshots/{id}/restore", "restore"),
+    (Method::POST, "/forget", "forget"),
+    (Method::POST, "/prune", "prune"),
+    (Method::POST, "/check", "check"),
+    (Method::POST, "/repair", "repair"),
+    (Method::POST, "/snapshots/{id}/copy", "copy"),
+    (Method::DELETE, "/snapshots/{id}", "delete"),
+    (Method::POST, "/snapshots/forget-bulk", "delete"),
+];
+
+// how long delivering a webhook may take
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// posts the outcome of an operation to the webhook url in the background, delivery
+// failures are only logged. the text field makes the payload a valid slack message
+pub fn notify(url: Option<String>, operation: &str, duration: Duration, error: Option<String>) {
+    let Some(url) = url.filter(|url| !url.is_empty()) else {
+        return;
+    };
+
+    let status = if error.is_some() {
+        "failed"
+    } else {
+        "succeeded"
+    };
+    let payload = json!({
+        "operation": operation,
+        "status": status,
+        "duration_ms": duration.as_millis() as u64,
+        "error": error,
+        "text": format!("restic {} {} after {:.1}s", operation, status, duration.as_secs_f64()),
+    });
+    let operation = operation.to_string();
+
+    actix_web::rt::spawn(async move {
+        let client = awc::Client::builder().timeout(WEBHOOK_TIMEOUT).finish();
+        match client.post(&url).send_json(&payload).await {
+            Ok(response) if response.status().is_success() => {
+                tracing::debug!(operation, "delivered webhook");
+            }
+            Ok(response) => {
+                tracing::warn!(operation, status = %response.status(), "webhook was rejected");
+            }
+            Err(e) => tracing::warn!(operation, error = %e, "failed to deliver webhook"),
+        }
+    });
+}
+
+// reads the error from a failed operation's json body, None when restic never ran, e.g.
+// for invalid requests or while another write operation is running
+fn operation_error(status: StatusCode, body: &[u8]) -> Option<String> {
+    let body: Value = serde_json::from_slice(body).unwrap_or_default();
+    if !status.is_server_error() && !body["restic_exit_code"].is_number() {
+        return None;
+    }
+    Some(
+        body["error"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| status.to_string()),
+    )
+}
+
+// middleware reporting finished write operations to server.webhook_url, operations
+// started as background jobs are reported by the job runner when they finish instead
+pub async fn notify_webhook(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let operation = req.match_pattern().and_then(|pattern| {
+        NOTIFIED_ROUTES
+            .iter()
+            .find(|(method, route, _)| req.method() == method && pattern == *route)
+            .map(|(_, _, operation)| *operation)
+    });
+    let url = match (operation, req.app_data::<web::Data<AppState>>()) {
+        (Some(_), Some(data)) => data.config.lock().await.server.webhook_url.clone(),
+        _ => None,
+    };
+
+    let started = Instant::now();
+    let res = next.call(req).await?.map_into_boxed_body();
+    let (Some(operation), Some(url)) = (operation, url) else {
+        return Ok(res);
+    };
+
+    let status = res.status();
+    if status == StatusCode::ACCEPTED {
+        return Ok(res);
+    }
+    if status.is_success() {
+        notify(Some(url), operation, started.elapsed(), None);
+        return Ok(res);
+    }
+
+    // the body of a failed request is a small json error, so it is read and put back
+    let (req, res) = res.into_parts();
+    let (head, response_body) = res.into_parts();
+    let bytes = body::to_bytes(response_body)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("failed to read response"))?;
+    if let Some(error) = operation_error(status, &bytes) {
+        notify(Some(url), operation, started.elapsed(), Some(error));
+    }
+
+    let res: HttpResponse = head.set_body(bytes).map_into_boxed_body();
+    Ok(ServiceResponse::new(req, res))
+}